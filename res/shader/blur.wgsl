@@ -1,34 +1,44 @@
 // Separable Gaussian blur — texture fragment shader (for postprocessors)
 // Preamble provides: sheet(), sheetSize(), texture_uniforms, TextureFragmentInput
-// Pass horizontal direction via: program.set_uniform("horizontal", &true)
-// The engine maps "horizontal" to texture_uniforms._rd_flags.x automatically.
+// Uniforms (set in this order, see bloom.rs): horizontal, radius, sigma
+// -> texture_uniforms._rd_flags.x, .y, .z
+// Samples radius taps on each side, 1.2 texels apart, weighted by a Gaussian
+// of the given standard deviation (in taps). Taps outside the texture are
+// skipped and the remaining weights renormalized. Radius 1 with sigma 1.656
+// gives the weights 0.3125 / 0.375 / 0.3125. The result is not clamped.
 
-const W0: f32 = 0.3125;
-const W1: f32 = 0.375;
-const W2: f32 = 0.3125;
+const MAX_RADIUS: i32 = 8;
 
 @fragment
 fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
     let horizontal = texture_uniforms._rd_flags.x > 0.5;
+    let radius = clamp(i32(texture_uniforms._rd_flags.y + 0.5), 1, MAX_RADIUS);
+    let sigma = max(texture_uniforms._rd_flags.z, 0.001);
     let tex_size = vec2<f32>(sheetSize());
 
-    var offset: vec2<f32>;
+    var step: vec2<f32>;
     if horizontal {
-        offset = vec2<f32>(1.2 / tex_size.x, 0.0);
+        step = vec2<f32>(1.2 / tex_size.x, 0.0);
     } else {
-        offset = vec2<f32>(0.0, 1.2 / tex_size.y);
+        step = vec2<f32>(0.0, 1.2 / tex_size.y);
     }
 
-    let s0 = input.v_tex_coords - offset;
-    let s2 = input.v_tex_coords + offset;
-
-    var color = W1 * sheet(input.v_tex_coords);
-    if s0.x > offset.x && s0.y > offset.y {
-        color += W0 * sheet(s0);
-    }
-    if s2.x < 1.0 - offset.x && s2.y < 1.0 - offset.y {
-        color += W2 * sheet(s2);
+    var color = sheet(input.v_tex_coords);
+    var total = 1.0;
+    for (var i = 1; i <= radius; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        let offset = f32(i) * step;
+        let s0 = input.v_tex_coords - offset;
+        let s2 = input.v_tex_coords + offset;
+        if s0.x > 0.0 && s0.y > 0.0 {
+            color += weight * sheet(s0);
+            total += weight;
+        }
+        if s2.x < 1.0 && s2.y < 1.0 {
+            color += weight * sheet(s2);
+            total += weight;
+        }
     }
 
-    return clamp(color, vec4<f32>(0.0), vec4<f32>(1.0));
+    return color / total;
 }
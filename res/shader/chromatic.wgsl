@@ -0,0 +1,18 @@
+// Chromatic aberration — texture fragment shader (for postprocessors)
+// Shifts red outwards and blue inwards, by `offset` pixels at the screen edges
+// (none at the center).
+// Uniforms (see effects.rs): offset -> texture_uniforms._rd_flags.x
+
+@fragment
+fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
+    let offset = texture_uniforms._rd_flags.x;
+    let tex_size = vec2<f32>(sheetSize());
+
+    let direction = (input.v_tex_coords - vec2<f32>(0.5)) * 2.0;
+    let shift = direction * offset / tex_size;
+    let red = sheet(input.v_tex_coords - shift);
+    let center = sheet(input.v_tex_coords);
+    let blue = sheet(input.v_tex_coords + shift);
+    // premultiplied: each channel keeps its own coverage
+    return vec4<f32>(red.r, center.g, blue.b, max(max(red.a, center.a), blue.a));
+}
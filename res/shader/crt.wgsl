@@ -0,0 +1,25 @@
+// CRT — texture fragment shader (for postprocessors)
+// Barrel-curved screen (black outside the curved area) with scanlines every
+// 3 pixels. Like the vignette, the darkening is output as alpha, so it covers
+// what is below the processed layer too.
+// Uniforms (set in this order, see effects.rs): curvature, scanlines
+// -> texture_uniforms._rd_flags.x, .y
+
+@fragment
+fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
+    let curvature = texture_uniforms._rd_flags.x;
+    let scanlines = clamp(texture_uniforms._rd_flags.y, 0.0, 1.0);
+    let tex_size = vec2<f32>(sheetSize());
+
+    let centered = input.v_tex_coords * 2.0 - vec2<f32>(1.0);
+    let curved = centered * (1.0 + curvature * dot(centered, centered) * 0.25);
+    let uv = curved * 0.5 + vec2<f32>(0.5);
+    let color = sheet(clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)));
+
+    let line = 0.5 + 0.5 * cos(uv.y * tex_size.y * 2.0943951);
+    let dark = scanlines * line;
+    let shaded = vec4<f32>(color.rgb * (1.0 - dark), color.a * (1.0 - dark) + dark);
+
+    let inside = all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0));
+    return select(vec4<f32>(0.0, 0.0, 0.0, 1.0), shaded, inside);
+}
@@ -0,0 +1,29 @@
+// Color grading — texture fragment shader (for postprocessors)
+// Maps colors through a LUT: a 256x16 strip of 16 slices (blue), each with red
+// along x and green along y. `strength` blends between original and graded.
+// Uniforms (see effects.rs): strength -> texture_uniforms._rd_flags.x,
+// lut -> @group(0) @binding(3) (the first texture uniform).
+
+@group(0) @binding(3) var lut: texture_2d<f32>;
+
+fn grade(color: vec3<f32>) -> vec3<f32> {
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    let slice = c.b * 15.0;
+    let slice0 = floor(slice);
+    let slice1 = min(slice0 + 1.0, 15.0);
+    let x = (c.r * 15.0 + 0.5) / 256.0;
+    let y = (c.g * 15.0 + 0.5) / 16.0;
+    let graded0 = textureSample(lut, _rd_sampler, vec2<f32>(x + slice0 / 16.0, y)).rgb;
+    let graded1 = textureSample(lut, _rd_sampler, vec2<f32>(x + slice1 / 16.0, y)).rgb;
+    return mix(graded0, graded1, slice - slice0);
+}
+
+@fragment
+fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
+    let strength = clamp(texture_uniforms._rd_flags.x, 0.0, 1.0);
+    let color = sheet(input.v_tex_coords);
+    // grade the straight (not premultiplied) color
+    let straight = color.rgb / max(color.a, 0.0001);
+    let graded = mix(straight, grade(straight), strength);
+    return vec4<f32>(graded * color.a, color.a);
+}
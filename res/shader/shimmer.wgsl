@@ -0,0 +1,17 @@
+// Shimmer — texture fragment shader (custom program, see create_program)
+// Pulses the brightness and sweeps a diagonal highlight band across the
+// processed layer.
+// Uniforms (set in this order by the script): time, amount
+// -> texture_uniforms._rd_flags.x, .y
+
+@fragment
+fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
+    let time = texture_uniforms._rd_flags.x;
+    let amount = texture_uniforms._rd_flags.y;
+
+    let color = sheet(input.v_tex_coords);
+    let sweep = fract(input.v_tex_coords.x + input.v_tex_coords.y - time * 0.5);
+    let band = 1.0 - smoothstep(0.0, 0.08, abs(sweep - 0.5));
+    let brightness = 1.0 + amount * (0.5 + 0.5 * sin(time * 6.0) + band);
+    return vec4<f32>(color.rgb * brightness, color.a);
+}
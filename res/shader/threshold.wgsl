@@ -0,0 +1,19 @@
+// Bloom threshold — texture fragment shader (for postprocessors)
+// Keeps the bright parts of the input: pixels whose brightest channel is below
+// the threshold are dropped, with a soft (quadratic) transition of width
+// 2 * knee around it. Threshold 0 passes everything through.
+// Uniforms (set in this order, see bloom.rs): threshold, knee
+// -> texture_uniforms._rd_flags.x, .y
+
+@fragment
+fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
+    let threshold = texture_uniforms._rd_flags.x;
+    let knee = texture_uniforms._rd_flags.y;
+
+    let color = sheet(input.v_tex_coords);
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.00001);
+    let contribution = max(soft, brightness - threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
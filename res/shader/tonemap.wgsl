@@ -0,0 +1,20 @@
+// Tone mapping — texture fragment shader (final pass of the HDR pipeline)
+// Maps the unclamped (F16) frame to the display range.
+// Uniforms (set in this order, see effects.rs): exposure, mapping
+// -> texture_uniforms._rd_flags.x, .y
+// mapping: 0 = none (clamp), 1 = Reinhard, 2 = ACES (Narkowicz fit)
+
+@fragment
+fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
+    let exposure = texture_uniforms._rd_flags.x;
+    let mapping = i32(texture_uniforms._rd_flags.y + 0.5);
+
+    let color = sheet(input.v_tex_coords);
+    var rgb = max(color.rgb * exposure, vec3<f32>(0.0));
+    if mapping == 1 {
+        rgb = rgb / (vec3<f32>(1.0) + rgb);
+    } else if mapping == 2 {
+        rgb = (rgb * (2.51 * rgb + 0.03)) / (rgb * (2.43 * rgb + 0.59) + 0.14);
+    }
+    return vec4<f32>(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)), clamp(color.a, 0.0, 1.0));
+}
@@ -0,0 +1,18 @@
+// Vignette — texture fragment shader (for postprocessors)
+// Darkens towards the screen edges. Outputs darkening alpha as well, so what is
+// below the processed layer is darkened too (premultiplied alpha blending).
+// Uniforms (set in this order, see effects.rs): strength, radius, softness
+// -> texture_uniforms._rd_flags.x, .y, .z
+
+@fragment
+fn main(input: TextureFragmentInput) -> @location(0) vec4<f32> {
+    let strength = texture_uniforms._rd_flags.x;
+    let radius = texture_uniforms._rd_flags.y;
+    let softness = max(texture_uniforms._rd_flags.z, 0.001);
+
+    let color = sheet(input.v_tex_coords);
+    // distance from the center, 1.0 at the corners
+    let distance = length((input.v_tex_coords - vec2<f32>(0.5)) * 1.4142);
+    let dark = clamp(strength * smoothstep(radius, radius + softness, distance), 0.0, 1.0);
+    return vec4<f32>(color.rgb * (1.0 - dark), color.a * (1.0 - dark) + dark);
+}
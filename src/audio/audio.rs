@@ -0,0 +1,339 @@
+mod bus;
+mod clock;
+mod groups;
+mod limits;
+mod music;
+mod output;
+mod spatial;
+mod voice;
+
+pub use self::bus::{Bus, Buses, BUS_COUNT};
+pub use self::clock::GameClock;
+pub use self::groups::{SoundGroups, SoundGroup, Variant};
+pub use self::limits::{Limits, Limit, Admit, VoiceInfo};
+pub use self::music::{Music, Playlist, Fade};
+pub use self::output::Output;
+pub use self::spatial::Listener;
+pub use self::voice::{Voice, Placement, Mix};
+
+use crate::prelude::*;
+use crate::settings::AudioSettings;
+use crate::sound::Sound;
+use rodio::Source;
+use rodio::mixer::Mixer;
+
+/// A sound started by `Audio`, recorded while the play log is enabled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayedSound {
+    pub filename: String,
+    pub bus     : Bus,
+    /// Time since the `Audio` was created.
+    pub time    : Duration,
+}
+
+/// Audio output: plays sounds on buses (master, sfx, music, ui) mixed into the
+/// rodio output mixer, and streamed music. Bus volumes apply to playing sounds too.
+/// Sound effects follow the game time: they slow down with its rate and pause
+/// with the game.
+pub struct Audio {
+    mixer   : Mixer,
+    buses   : Arc<Buses>,
+    /// Game time rate followed by gameplay (sfx) sounds.
+    clock   : Arc<GameClock>,
+    /// Whether game time is stopping (its target rate is 0, e.g. while pausing).
+    stopping: bool,
+    /// Elapsed game time (see `set_game_time`).
+    game_time: Duration,
+    /// Sound cache, decoded (loaded on first play or preload).
+    sounds  : HashMap<String, Sound>,
+    /// Memory limit of the sound cache in bytes (see `preload`).
+    cache_limit: usize,
+    /// Sound effects still playing (positional ones are updated each frame).
+    voices  : Vec<Voice>,
+    /// Voice limits per sound group (res/sound/limits.toml).
+    limits  : Limits,
+    /// Sound groups played with `play_group` (res/sound/groups.toml).
+    groups  : SoundGroups,
+    /// Variant, volume and pitch picks of sound groups.
+    rng     : Rng,
+    /// Last start time per voice limit group and whether it was in game time
+    /// (for `Limit::min_interval`, see `clock_time`).
+    last_start: HashMap<(String, bool), Duration>,
+    /// Listener position set by the script (None = screen center).
+    listener_position: Option<Vec2>,
+    /// Listener positional sounds are heard from (see `update`).
+    listener: Listener,
+    /// Music player (music bus).
+    pub music: Music,
+    /// Time of the last `update` (music fades run on real time).
+    last_update: Instant,
+    created : Instant,
+    /// Current time when simulated (offline rendering, see `simulate_time`).
+    simulated_now: Option<Instant>,
+    /// Sounds started since the play log was enabled (None = disabled).
+    play_log: Option<Vec<PlayedSound>>,
+}
+
+impl Audio {
+    /// Audio output on the given mixer, bus volumes taken from `settings`.
+    pub fn new(mixer: &Mixer, settings: &AudioSettings) -> Self {
+        let buses = Arc::new(Buses::new());
+        let audio = Audio {
+            mixer   : mixer.clone(),
+            music   : Music::new(mixer, &buses),
+            buses   : buses,
+            clock   : Arc::new(GameClock::new()),
+            stopping: false,
+            game_time: Duration::ZERO,
+            sounds  : HashMap::new(),
+            cache_limit: settings.sound_cache_mb * 1024 * 1024,
+            voices  : Vec::new(),
+            limits  : Limits::load(),
+            groups  : SoundGroups::load(),
+            rng     : Rng::new(345.6),
+            last_start: HashMap::new(),
+            listener_position: None,
+            listener: Listener { position: Vec2(0.0, 0.0), width: 0.0 },
+            last_update: Instant::now(),
+            created : Instant::now(),
+            simulated_now: None,
+            play_log: None,
+        };
+        for bus in Bus::ALL {
+            let bus_settings = settings.bus(bus);
+            audio.buses.set_volume(bus, bus_settings.volume);
+            audio.buses.set_muted(bus, bus_settings.muted);
+        }
+        audio
+    }
+
+    /// Load and decode a sound file into the cache, unless cached already
+    /// (avoids the decode on its first play). Sounds not fitting into the
+    /// remaining cache memory stay encoded (decoded while playing).
+    pub fn preload(&mut self, filename: &str) -> Result<(), String> {
+        if !self.sounds.contains_key(filename) {
+            let remaining = self.cache_limit.saturating_sub(self.cache_memory());
+            let sound = Sound::load_limited(filename, remaining).map_err(|e| e.to_string())?;
+            self.sounds.insert(filename.to_string(), sound);
+        }
+        Ok(())
+    }
+
+    /// Memory used by the cached sounds in bytes.
+    pub fn cache_memory(&self) -> usize {
+        self.sounds.values().map(|sound| sound.memory()).sum()
+    }
+
+    /// Play a sound file on `bus`, panned and attenuated by its placement.
+    /// Files are loaded (decoded) on first use and cached. The sound's voice
+    /// limits apply: it may stop older voices of its group, or be dropped.
+    pub fn play(&mut self, filename: &str, bus: Bus, placement: Placement) -> Result<(), String> {
+        self.start(filename, bus, placement, 1.0, 1.0)
+    }
+
+    /// Play a sound group of the manifest: a variant picked by weight, with a
+    /// random volume and pitch from the group's ranges, on the group's bus.
+    pub fn play_group(&mut self, name: &str, placement: Placement) -> Result<(), String> {
+        let group = self.groups.get(name).ok_or_else(|| format!("unknown sound group '{}'", name))?;
+        let filename = group.variant_at(self.rng.range(0.0, 1.0)).ok_or_else(|| format!("sound group '{}' has no variants", name))?;
+        let volume = self.rng.range(group.volume.0, group.volume.1);
+        let pitch = self.rng.range(group.pitch.0, group.pitch.1);
+        let bus = group.bus;
+        self.start(&filename, bus, placement, volume, pitch)
+    }
+
+    /// Files of a sound group (e.g. to preload them).
+    pub fn group_files(&self, name: &str) -> Vec<String> {
+        self.groups.get(name).map(|group| group.files().collect()).unwrap_or_default()
+    }
+
+    /// Start a voice playing `filename` at `volume` and `pitch` (playback speed),
+    /// subject to its voice limits. Sounds following the game time are dropped
+    /// while it is stopped or stopping (rather than starting all at once when
+    /// it resumes).
+    fn start(&mut self, filename: &str, bus: Bus, placement: Placement, volume: f32, pitch: f32) -> Result<(), String> {
+        self.preload(filename)?;
+        if bus.follows_game_time() && (self.stopping || self.clock.speed().is_none()) {
+            return Ok(());
+        }
+
+        let now = self.now();
+        let group = self.limits.group_of(filename);
+        let clock_time = self.clock_time(bus);
+        let last_start_key = (group.clone(), bus.follows_game_time());
+        self.voices.retain(|voice| !voice.finished());
+        let infos: Vec<VoiceInfo> = self.voices.iter().map(|voice| VoiceInfo { group: &voice.group, filename: &voice.filename, started: voice.started }).collect();
+        let steal = match self.limits.admit(&group, filename, &infos, self.last_start.get(&last_start_key).copied(), clock_time) {
+            Admit::Play(steal) => steal,
+            Admit::Drop => return Ok(()),
+        };
+        for &index in steal.iter().rev() {
+            self.voices.remove(index).stop();
+        }
+
+        let source = self.sounds[filename].source().speed(pitch).amplify(volume);
+        self.last_start.insert(last_start_key, clock_time);
+        let (buses, clock) = (self.buses.clone(), self.clock.clone());
+        let mix = move || Mix {
+            gain    : buses.gain(bus),
+            speed   : if bus.follows_game_time() { clock.speed() } else { Some(1.0) },
+        };
+        self.voices.push(Voice::start(&self.mixer, mix, source, filename, group, placement, &self.listener));
+        if let Some(log) = &mut self.play_log {
+            log.push(PlayedSound { filename: filename.to_string(), bus, time: now - self.created });
+        }
+        Ok(())
+    }
+
+    /// Record the sounds started from now on (e.g. to check in tests which
+    /// sounds played and when), or stop recording.
+    pub fn set_play_log(&mut self, enabled: bool) {
+        self.play_log = if enabled { Some(self.play_log.take().unwrap_or_default()) } else { None };
+    }
+
+    /// The sounds started since the last call (empty if the log is disabled).
+    pub fn take_play_log(&mut self) -> Vec<PlayedSound> {
+        self.play_log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Hear positional sounds from `position` (screen pixels), e.g. a camera
+    /// position; None = the screen center.
+    pub fn set_listener(&mut self, position: Option<Vec2>) {
+        self.listener_position = position;
+    }
+
+    /// Set the elapsed game time, its rate, which sound effects and the music
+    /// follow (see `Music::set_pause_with_game`), and the rate it is heading to:
+    /// no new sound effects start once that is 0. Set before the frame's sounds play.
+    pub fn set_game_time(&mut self, elapsed: Duration, rate: f32, target_rate: f32) {
+        self.game_time = elapsed;
+        self.clock.set_rate(rate);
+        self.stopping = target_rate <= 0.0;
+    }
+
+    /// Per-frame update: music fades and playlist, positional sounds.
+    /// `screen_size` places the default listener and scales panning;
+    /// `position_of` returns the position of an entity followed by a sound
+    /// (None once it is gone).
+    pub fn update(&mut self, screen_size: (u32, u32), position_of: impl Fn(u64) -> Option<Vec2>) {
+        let now = self.now();
+        let delta = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+        self.music.update(delta, self.clock.rate());
+
+        let (width, height) = (screen_size.0 as f32, screen_size.1 as f32);
+        self.listener = Listener {
+            position: self.listener_position.unwrap_or(Vec2(width * 0.5, height * 0.5)),
+            width   : width,
+        };
+        self.voices.retain(|voice| !voice.finished());
+        for voice in self.voices.iter_mut() {
+            voice.update(&self.listener, &position_of);
+        }
+    }
+
+    /// Bus volumes and mute state.
+    pub fn buses(&self) -> &Buses {
+        &self.buses
+    }
+
+    /// Run music fades and voice limits on a simulated clock advanced by
+    /// `advance_time` instead of real time (offline rendering).
+    pub fn simulate_time(&mut self) {
+        self.simulated_now = Some(self.last_update);
+    }
+
+    /// Advance the simulated clock (see `simulate_time`).
+    pub fn advance_time(&mut self, delta: Duration) {
+        if let Some(now) = &mut self.simulated_now {
+            *now += delta;
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.simulated_now.unwrap_or_else(Instant::now)
+    }
+
+    /// Time voice limits measure on `bus`: the game time for sounds following
+    /// it, else the time since the `Audio` was created.
+    fn clock_time(&self, bus: Bus) -> Duration {
+        if bus.follows_game_time() { self.game_time } else { self.now() - self.created }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEW: &str = "res/sound/projectile/pew2.ogg";
+
+    fn audio(output: &Output) -> Audio {
+        let mut audio = Audio::new(output.mixer(), &AudioSettings::default());
+        audio.set_play_log(true);
+        audio
+    }
+
+    #[test]
+    fn play_log_records_started_sounds() {
+        let output = Output::null();
+        let mut audio = audio(&output);
+        audio.play(PEW, Bus::Ui, Placement::Center).unwrap();
+        audio.play_group("projectile/powerup", Placement::At(Vec2(10.0, 20.0))).unwrap();
+        let log = audio.take_play_log();
+        assert_eq!(log.iter().map(|played| (played.filename.as_str(), played.bus)).collect::<Vec<_>>(),
+            vec![ (PEW, Bus::Ui), ("res/sound/projectile/powerup1.ogg", Bus::Sfx) ]);
+        assert!(log[0].time <= log[1].time);
+        assert!(audio.take_play_log().is_empty());
+        assert!(audio.play("res/sound/missing.ogg", Bus::Sfx, Placement::Center).is_err());
+        assert!(audio.play_group("missing", Placement::Center).is_err());
+        assert!(audio.take_play_log().is_empty());
+    }
+
+    #[test]
+    fn sounds_beyond_the_cache_limit_stay_encoded() {
+        let output = Output::null();
+        let mut audio = Audio::new(output.mixer(), &AudioSettings { sound_cache_mb: 0, ..AudioSettings::default() });
+        audio.preload(PEW).unwrap();
+        assert_eq!(audio.cache_memory(), fs::metadata(PEW).unwrap().len() as usize);
+        let mut audio = Audio::new(output.mixer(), &AudioSettings::default());
+        audio.preload(PEW).unwrap();
+        assert!(audio.cache_memory() > fs::metadata(PEW).unwrap().len() as usize);
+    }
+
+    #[test]
+    fn game_time_stops_sound_effects_only() {
+        let output = Output::null();
+        let mut audio = audio(&output);
+        audio.set_game_time(Duration::ZERO, 0.0, 0.0);
+        audio.play(PEW, Bus::Sfx, Placement::Center).unwrap();
+        audio.play(PEW, Bus::Ui, Placement::Center).unwrap();
+        assert_eq!(audio.take_play_log().iter().map(|played| played.bus).collect::<Vec<_>>(), vec![ Bus::Ui ]);
+    }
+
+    #[test]
+    fn pausing_game_time_drops_new_sound_effects() {
+        let output = Output::null();
+        let mut audio = audio(&output);
+        audio.set_game_time(Duration::ZERO, 0.6, 0.0);
+        audio.play(PEW, Bus::Sfx, Placement::Center).unwrap();
+        audio.play(PEW, Bus::Ui, Placement::Center).unwrap();
+        audio.set_game_time(Duration::ZERO, 0.3, 1.0);
+        audio.play(PEW, Bus::Sfx, Placement::Center).unwrap();
+        assert_eq!(audio.take_play_log().iter().map(|played| played.bus).collect::<Vec<_>>(), vec![ Bus::Ui, Bus::Sfx ]);
+    }
+
+    #[test]
+    fn min_interval_runs_on_game_time() {
+        let output = Output::null();
+        let mut audio = audio(&output);
+        // group "projectile/pew" (res/sound/limits.toml) has a min_interval
+        audio.set_game_time(Duration::from_secs(1), 1.0, 1.0);
+        audio.play(PEW, Bus::Sfx, Placement::Center).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        audio.play(PEW, Bus::Sfx, Placement::Center).unwrap();
+        assert_eq!(audio.take_play_log().len(), 1);
+        audio.set_game_time(Duration::from_secs(2), 1.0, 1.0);
+        audio.play(PEW, Bus::Sfx, Placement::Center).unwrap();
+        assert_eq!(audio.take_play_log().len(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Number of buses.
+pub const BUS_COUNT: usize = 4;
+
+/// Audio bus a sound is played on. Every bus is mixed into `Master`, so the
+/// effective gain of a sound is master volume * bus volume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    Master,
+    /// Sound effects (play_sound).
+    Sfx,
+    Music,
+    /// Menu and interface sounds.
+    Ui,
+}
+
+impl Bus {
+    pub const ALL: [Bus; BUS_COUNT] = [ Bus::Master, Bus::Sfx, Bus::Music, Bus::Ui ];
+
+    /// Bus from its API ID (`Api::BUS_*`, same order as `ALL`).
+    pub fn from_id(id: u32) -> Option<Bus> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Whether sounds on the bus follow the game time (see `GameClock`);
+    /// music and interface sounds keep playing in the menu.
+    pub fn follows_game_time(self) -> bool {
+        self == Bus::Sfx
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Bus::Master => "master",
+            Bus::Sfx => "sfx",
+            Bus::Music => "music",
+            Bus::Ui => "ui",
+        }
+    }
+}
+
+/// Volume and mute state of all buses, shared with the playing sources (which
+/// poll their gain, see `Voice::start`), so changes apply to sounds already playing.
+pub struct Buses {
+    /// Volume per bus (f32 bits).
+    volume  : [AtomicU32; BUS_COUNT],
+    muted   : [AtomicBool; BUS_COUNT],
+}
+
+impl Buses {
+    /// All buses at full volume, unmuted.
+    pub fn new() -> Self {
+        Buses {
+            volume  : std::array::from_fn(|_| AtomicU32::new(1.0f32.to_bits())),
+            muted   : std::array::from_fn(|_| AtomicBool::new(false)),
+        }
+    }
+
+    /// Set the volume of a bus (clamped to 0..=1).
+    pub fn set_volume(&self, bus: Bus, volume: f32) {
+        self.volume[bus.index()].store(volume.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn volume(&self, bus: Bus) -> f32 {
+        f32::from_bits(self.volume[bus.index()].load(Ordering::Relaxed))
+    }
+
+    pub fn set_muted(&self, bus: Bus, muted: bool) {
+        self.muted[bus.index()].store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self, bus: Bus) -> bool {
+        self.muted[bus.index()].load(Ordering::Relaxed)
+    }
+
+    /// Effective gain of a sound on `bus`: bus volume * master volume, 0 if
+    /// either is muted.
+    pub fn gain(&self, bus: Bus) -> f32 {
+        let own = |bus: Bus| if self.is_muted(bus) { 0.0 } else { self.volume(bus) };
+        if bus == Bus::Master { own(bus) } else { own(bus) * own(Bus::Master) }
+    }
+}
+
+impl Default for Buses {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bus, Buses};
+
+    #[test]
+    fn gain_combines_bus_and_master() {
+        let buses = Buses::new();
+        buses.set_volume(Bus::Master, 0.5);
+        buses.set_volume(Bus::Music, 0.5);
+        assert_eq!(buses.gain(Bus::Music), 0.25);
+        assert_eq!(buses.gain(Bus::Sfx), 0.5);
+        assert_eq!(buses.gain(Bus::Master), 0.5);
+        buses.set_volume(Bus::Ui, 3.0);
+        assert_eq!(buses.volume(Bus::Ui), 1.0);
+    }
+
+    #[test]
+    fn mute_silences_without_losing_volume() {
+        let buses = Buses::new();
+        buses.set_volume(Bus::Sfx, 0.8);
+        buses.set_muted(Bus::Sfx, true);
+        assert_eq!(buses.gain(Bus::Sfx), 0.0);
+        buses.set_muted(Bus::Sfx, false);
+        assert_eq!(buses.gain(Bus::Sfx), 0.8);
+        buses.set_muted(Bus::Master, true);
+        assert_eq!(buses.gain(Bus::Sfx), 0.0);
+        assert_eq!(Bus::from_id(2), Some(Bus::Music));
+        assert_eq!(Bus::from_id(4), None);
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Slowest playback speed of sounds following the game time; below it they
+/// keep this speed until the rate reaches 0 and they pause.
+pub const MIN_SPEED: f32 = 0.25;
+
+/// Game time rate (`Timeframe::rate`) shared with playing sources. Gameplay
+/// sounds follow it: they slow down (lower pitch) with the rate, pause when
+/// game time stops and resume with it.
+pub struct GameClock {
+    /// Rate (f32 bits).
+    rate    : AtomicU32,
+}
+
+impl GameClock {
+    /// Clock at normal rate.
+    pub fn new() -> Self {
+        GameClock { rate: AtomicU32::new(1.0f32.to_bits()) }
+    }
+
+    pub fn set_rate(&self, rate: f32) {
+        self.rate.store(rate.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn rate(&self) -> f32 {
+        f32::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+
+    /// Playback speed of a sound following the clock, None = paused.
+    pub fn speed(&self) -> Option<f32> {
+        speed_at(self.rate())
+    }
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Playback speed at game time `rate`, None = paused.
+pub fn speed_at(rate: f32) -> Option<f32> {
+    if rate > 0.0 { Some(rate.max(MIN_SPEED)) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameClock, speed_at, MIN_SPEED};
+
+    #[test]
+    fn speed_follows_rate_and_pauses_at_zero() {
+        assert_eq!(speed_at(1.0), Some(1.0));
+        assert_eq!(speed_at(0.5), Some(0.5));
+        assert_eq!(speed_at(1.5), Some(1.5));
+        assert_eq!(speed_at(0.01), Some(MIN_SPEED));
+        assert_eq!(speed_at(0.0), None);
+        let clock = GameClock::new();
+        assert_eq!(clock.speed(), Some(1.0));
+        clock.set_rate(-1.0);
+        assert_eq!(clock.rate(), 0.0);
+        assert_eq!(clock.speed(), None);
+    }
+}
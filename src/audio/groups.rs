@@ -0,0 +1,144 @@
+use crate::prelude::*;
+use super::Bus;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Sound group manifest.
+pub const PATH: &str = "res/sound/groups.toml";
+
+/// Sound directory; variant files are relative to it.
+const SOUND_DIR: &str = "res/sound/";
+
+/// A sound file of a group.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Variant {
+    /// File path relative to res/sound.
+    pub file    : String,
+    /// Relative probability of picking this variant.
+    #[serde(default = "default_weight")]
+    pub weight  : f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// A sound group: variants picked at random (by weight), played with a random
+/// volume and pitch from the given ranges, on a bus. Voice limits
+/// (res/sound/limits.toml) apply per variant file, as for single sounds.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SoundGroup {
+    pub variants: Vec<Variant>,
+    /// Volume range (min, max), 1 = unchanged.
+    pub volume  : (f32, f32),
+    /// Pitch (playback speed) range (min, max), 1 = unchanged.
+    pub pitch   : (f32, f32),
+    pub bus     : Bus,
+}
+
+impl Default for SoundGroup {
+    fn default() -> Self {
+        SoundGroup { variants: Vec::new(), volume: (1.0, 1.0), pitch: (1.0, 1.0), bus: Bus::Sfx }
+    }
+}
+
+impl SoundGroup {
+    /// The variant file (path including res/sound) at `roll` (0..1) of the
+    /// cumulative weights.
+    pub fn variant_at(&self, roll: f32) -> Option<String> {
+        let total: f32 = self.variants.iter().map(|variant| variant.weight.max(0.0)).sum();
+        let mut remaining = roll.clamp(0.0, 1.0) * total;
+        let last = self.variants.iter().rev().find(|variant| variant.weight > 0.0)?;
+        let picked = self.variants.iter().find(|variant| {
+            remaining -= variant.weight.max(0.0);
+            variant.weight > 0.0 && remaining < 0.0
+        }).unwrap_or(last);
+        Some(format!("{}{}", SOUND_DIR, picked.file))
+    }
+
+    /// All variant files (paths including res/sound).
+    pub fn files(&self) -> impl Iterator<Item=String> + '_ {
+        self.variants.iter().map(|variant| format!("{}{}", SOUND_DIR, variant.file))
+    }
+}
+
+/// Sound group manifest (res/sound/groups.toml): group name -> group.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SoundGroups {
+    pub groups: BTreeMap<String, SoundGroup>,
+}
+
+impl SoundGroups {
+    /// Loads the manifest (no groups if the file is missing or invalid).
+    pub fn load() -> SoundGroups {
+        match fs::read_to_string(PATH) {
+            Ok(source) => Self::parse(&source).unwrap_or_else(|e| {
+                eprintln!("audio: ignoring '{}': {}", PATH, e);
+                SoundGroups::default()
+            }),
+            Err(e) => {
+                eprintln!("audio: cannot load '{}': {}", PATH, e);
+                SoundGroups::default()
+            }
+        }
+    }
+
+    /// Parses a manifest from TOML source.
+    pub fn parse(source: &str) -> Result<SoundGroups, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SoundGroup> {
+        self.groups.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_parses_with_defaults() {
+        let groups = SoundGroups::parse("
+            [groups.pew]
+            variants = [ { file = \"a.ogg\" }, { file = \"b.ogg\", weight = 3.0 } ]
+            pitch = [ 0.9, 1.1 ]
+
+            [groups.click]
+            variants = [ { file = \"click.ogg\" } ]
+            bus = \"ui\"
+        ").unwrap();
+        let pew = groups.get("pew").unwrap();
+        assert_eq!(pew.variants[0].weight, 1.0);
+        assert_eq!(pew.pitch, (0.9, 1.1));
+        assert_eq!(pew.volume, (1.0, 1.0));
+        assert_eq!(pew.bus, Bus::Sfx);
+        assert_eq!(groups.get("click").unwrap().bus, Bus::Ui);
+    }
+
+    #[test]
+    fn variants_are_picked_by_weight() {
+        let group = SoundGroups::parse("
+            [groups.g]
+            variants = [ { file = \"a.ogg\" }, { file = \"off.ogg\", weight = 0.0 }, { file = \"b.ogg\", weight = 3.0 } ]
+        ").unwrap().groups.remove("g").unwrap();
+        assert_eq!(group.variant_at(0.0).unwrap(), "res/sound/a.ogg");
+        assert_eq!(group.variant_at(0.24).unwrap(), "res/sound/a.ogg");
+        assert_eq!(group.variant_at(0.26).unwrap(), "res/sound/b.ogg");
+        assert_eq!(group.variant_at(1.0).unwrap(), "res/sound/b.ogg");
+        assert_eq!(SoundGroup::default().variant_at(0.5), None);
+    }
+
+    #[test]
+    fn shipped_manifest_references_existing_files() {
+        let groups = SoundGroups::parse(&fs::read_to_string(PATH).unwrap()).unwrap();
+        assert!(groups.get("projectile/pew").is_some());
+        for group in groups.groups.values() {
+            for file in group.files() {
+                assert!(std::path::Path::new(&file).is_file(), "missing {}", file);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Voice limit configuration.
+pub const PATH: &str = "res/sound/limits.toml";
+
+/// Sound directory; group files are paths relative to it.
+const SOUND_DIR: &str = "res/sound/";
+
+/// Voice limits of a sound group.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Limit {
+    /// Sound files or directories of the group (relative to res/sound); none
+    /// = the group name is its path.
+    pub files           : Vec<String>,
+    /// Maximum concurrent voices of the group, 0 = unlimited.
+    pub max_voices      : usize,
+    /// Maximum concurrent voices per sound file of the group, 0 = unlimited.
+    pub max_per_sound   : usize,
+    /// Minimum interval in seconds between two starts within the group; plays
+    /// in between are dropped. Game time for sounds following it (see `admit`).
+    pub min_interval    : f32,
+    /// At the voice limit, stop the oldest voice for the new one (true) or drop
+    /// the new one (false).
+    pub steal           : bool,
+}
+
+impl Default for Limit {
+    fn default() -> Self {
+        Limit { files: Vec::new(), max_voices: 0, max_per_sound: 0, min_interval: 0.0, steal: true }
+    }
+}
+
+/// A playing voice, as seen by the limiter.
+#[derive(Clone, Copy, Debug)]
+pub struct VoiceInfo<'a> {
+    pub group   : &'a str,
+    pub filename: &'a str,
+    pub started : Instant,
+}
+
+/// Result of `Limits::admit`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Admit {
+    /// Play, after stopping the voices at these indices (oldest first).
+    Play(Vec<usize>),
+    /// Drop the new sound.
+    Drop,
+}
+
+/// Per-group voice limits (res/sound/limits.toml). A group lists its files or
+/// directories relative to res/sound (or is named by its path), and a sound
+/// belongs to the group with the innermost entry containing it, e.g. a group
+/// listing "projectile" holds projectile/pew2.ogg but not projectile_x.ogg;
+/// sounds in no group form a group of their own, limited by `default`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    pub default : Limit,
+    pub groups  : BTreeMap<String, Limit>,
+}
+
+impl Limits {
+    /// Loads the voice limits (no limits if the file is missing or invalid).
+    pub fn load() -> Limits {
+        match fs::read_to_string(PATH) {
+            Ok(source) => Self::parse(&source).unwrap_or_else(|e| {
+                eprintln!("audio: ignoring '{}': {}", PATH, e);
+                Limits::default()
+            }),
+            Err(_) => Limits::default(),
+        }
+    }
+
+    /// Parses limits from TOML source.
+    pub fn parse(source: &str) -> Result<Limits, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Group of a sound file: the configured group name, or the file name itself.
+    pub fn group_of(&self, filename: &str) -> String {
+        let relative = filename.strip_prefix(SOUND_DIR).unwrap_or(filename);
+        self.groups.iter()
+            .filter_map(|(group, limit)| {
+                let paths = if limit.files.is_empty() { std::slice::from_ref(group) } else { &limit.files[..] };
+                paths.iter().filter(|path| contains(path, relative)).map(|path| path.len()).max().map(|len| (len, group))
+            })
+            .max_by_key(|&(len, _)| len)
+            .map(|(_, group)| group.clone())
+            .unwrap_or_else(|| filename.to_string())
+    }
+
+    pub fn limit(&self, group: &str) -> &Limit {
+        self.groups.get(group).unwrap_or(&self.default)
+    }
+
+    /// Decide whether `filename` (in `group`) may start now, given the playing
+    /// voices and the group's last start time. `last_start` and `now` are on the
+    /// sound's clock (game time for sounds following it, so a pause doesn't
+    /// count towards `min_interval`); a clock set back (e.g. on a restart)
+    /// doesn't drop anything.
+    pub fn admit(&self, group: &str, filename: &str, voices: &[VoiceInfo], last_start: Option<Duration>, now: Duration) -> Admit {
+        let limit = self.limit(group);
+        if let Some(since) = last_start.and_then(|last_start| now.checked_sub(last_start)) {
+            if since.as_secs_f32() < limit.min_interval {
+                return Admit::Drop;
+            }
+        }
+        let mut in_group: Vec<usize> = (0..voices.len()).filter(|&i| voices[i].group == group).collect();
+        in_group.sort_by_key(|&i| voices[i].started);
+        let mut steal = Vec::new();
+        // per sound first: stealing the oldest voice of the same file also makes room in the group
+        if limit.max_per_sound > 0 {
+            let same: Vec<usize> = in_group.iter().copied().filter(|&i| voices[i].filename == filename).collect();
+            if same.len() >= limit.max_per_sound {
+                steal.extend_from_slice(&same[..=same.len() - limit.max_per_sound]);
+            }
+        }
+        if limit.max_voices > 0 {
+            let remaining: Vec<usize> = in_group.iter().copied().filter(|i| !steal.contains(i)).collect();
+            if remaining.len() >= limit.max_voices {
+                steal.extend_from_slice(&remaining[..=remaining.len() - limit.max_voices]);
+            }
+        }
+        if !steal.is_empty() && !limit.steal {
+            return Admit::Drop;
+        }
+        steal.sort_by_key(|&i| voices[i].started);
+        Admit::Play(steal)
+    }
+}
+
+/// Whether `path` (a file or directory relative to res/sound) is or contains
+/// the sound file `relative`, matching whole path components only.
+fn contains(path: &str, relative: &str) -> bool {
+    relative == path || relative.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+        [default]
+        max_per_sound = 2
+
+        [groups.\"projectile/pew\"]
+        files = [ \"projectile/pew1a.ogg\", \"projectile/pew2.ogg\" ]
+        max_voices = 3
+        min_interval = 0.05
+
+        [groups.\"damage\"]
+        max_voices = 1
+        steal = false
+    ";
+
+    fn voice(group: &'static str, filename: &'static str, start: Instant, secs: u64) -> VoiceInfo<'static> {
+        VoiceInfo { group, filename, started: start + Duration::from_secs(secs) }
+    }
+
+    #[test]
+    fn groups_match_listed_files_and_directories() {
+        let limits = Limits::parse(CONFIG).unwrap();
+        assert_eq!(limits.group_of("res/sound/projectile/pew1a.ogg"), "projectile/pew");
+        assert_eq!(limits.group_of("res/sound/projectile/pew2.ogg"), "projectile/pew");
+        assert_eq!(limits.group_of("res/sound/projectile/pew1b.ogg"), "res/sound/projectile/pew1b.ogg");
+        // a group without files is its own path, matched by whole components
+        assert_eq!(limits.group_of("res/sound/damage/explosion1.ogg"), "damage");
+        assert_eq!(limits.group_of("res/sound/damage.ogg"), "res/sound/damage.ogg");
+        assert_eq!(limits.group_of("res/sound/projectile/powerup1.ogg"), "res/sound/projectile/powerup1.ogg");
+        assert_eq!(limits.limit("res/sound/projectile/powerup1.ogg").max_per_sound, 2);
+    }
+
+    #[test]
+    fn steals_oldest_voices_at_the_limit() {
+        let limits = Limits::parse(CONFIG).unwrap();
+        let t = Instant::now();
+        let voices = [
+            voice("projectile/pew", "pew2", t, 2),
+            voice("projectile/pew", "pew1", t, 1),
+            voice("other", "x", t, 0),
+            voice("projectile/pew", "pew1", t, 3),
+        ];
+        let now = Duration::from_secs(4);
+        assert_eq!(limits.admit("projectile/pew", "pew1", &voices, None, now), Admit::Play(vec![ 1 ]));
+        assert_eq!(limits.admit("projectile/pew", "pew1", &voices[..2], None, now), Admit::Play(vec![]));
+        // default: two voices per sound
+        let voices = [ voice("a", "a", t, 0), voice("a", "a", t, 1) ];
+        assert_eq!(limits.admit("a", "a", &voices, None, now), Admit::Play(vec![ 0 ]));
+    }
+
+    #[test]
+    fn drops_within_min_interval_or_without_stealing() {
+        let limits = Limits::parse(CONFIG).unwrap();
+        let last_start = Duration::from_secs(10);
+        let now = last_start + Duration::from_millis(20);
+        assert_eq!(limits.admit("projectile/pew", "pew1", &[], Some(last_start), now), Admit::Drop);
+        assert_eq!(limits.admit("projectile/pew", "pew1", &[], Some(last_start), now + Duration::from_millis(30)), Admit::Play(vec![]));
+        // the clock went back (restart)
+        assert_eq!(limits.admit("projectile/pew", "pew1", &[], Some(last_start), Duration::ZERO), Admit::Play(vec![]));
+        let voices = [ voice("damage", "explosion1", Instant::now(), 0) ];
+        assert_eq!(limits.admit("damage", "explosion2", &voices, None, now), Admit::Drop);
+    }
+}
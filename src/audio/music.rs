@@ -0,0 +1,338 @@
+use crate::prelude::*;
+use super::{Bus, Buses};
+use rodio::{Decoder, Source};
+use rodio::mixer::Mixer;
+use rodio::source::Done;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+/// Interval at which a playing track picks up gain/pause/stop changes.
+const CONTROL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Linear volume fade.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fade {
+    from    : f32,
+    to      : f32,
+    elapsed : f32,
+    duration: f32,
+}
+
+impl Fade {
+    /// Fade from `from` to `to` over `duration` seconds (0 = jump to `to`).
+    pub fn new(from: f32, to: f32, duration: f32) -> Self {
+        Fade { from, to, elapsed: 0.0, duration: duration.max(0.0) }
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.elapsed = (self.elapsed + delta).min(self.duration);
+    }
+
+    pub fn value(&self) -> f32 {
+        if self.duration <= 0.0 {
+            self.to
+        } else {
+            lerp(&self.from, &self.to, self.elapsed / self.duration)
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Ordered list of music tracks, optionally repeating.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Playlist {
+    tracks  : Vec<String>,
+    /// Index of the track `next` returns.
+    next    : usize,
+    pub looping: bool,
+}
+
+impl Playlist {
+    /// Replace the playlist (next track = first).
+    pub fn set(&mut self, tracks: Vec<String>, looping: bool) {
+        self.tracks = tracks;
+        self.next = 0;
+        self.looping = looping;
+    }
+
+    pub fn push(&mut self, track: String) {
+        self.tracks.push(track);
+    }
+
+    pub fn clear(&mut self) {
+        self.set(Vec::new(), false);
+    }
+
+    /// Whether `next` returns another track.
+    pub fn has_next(&self) -> bool {
+        self.next < self.tracks.len() || (self.looping && !self.tracks.is_empty())
+    }
+
+    /// The next track to play, wrapping around if looping.
+    pub fn next(&mut self) -> Option<String> {
+        if self.next >= self.tracks.len() {
+            if !self.looping || self.tracks.is_empty() {
+                return None;
+            }
+            self.next = 0;
+        }
+        self.next += 1;
+        Some(self.tracks[self.next - 1].clone())
+    }
+}
+
+/// State shared between a `Track` and its playing source.
+struct TrackControl {
+    /// Fade gain (f32 bits), multiplied by the music bus gain.
+    gain    : AtomicU32,
+    paused  : AtomicBool,
+    stopped : AtomicBool,
+}
+
+/// A playing (or fading out) music track.
+struct Track {
+    control : Arc<TrackControl>,
+    /// 1 while the source is playing, 0 once it finished.
+    playing : Arc<AtomicUsize>,
+    fade    : Fade,
+    /// Seconds played (excluding pauses).
+    elapsed : f32,
+    /// Track length, if the decoder knows it.
+    duration: Option<f32>,
+    /// Duck with the game time rate and pause with the game (see
+    /// `Music::set_pause_with_game`, fixed when the track starts).
+    pause_with_game: bool,
+}
+
+impl Track {
+    /// Open `filename` (streamed from disk) and start playing it on the mixer.
+    fn start(mixer: &Mixer, buses: &Arc<Buses>, filename: &str, fade: Fade, pause_with_game: bool) -> Result<Track, String> {
+        let file = fs::File::open(filename).map_err(|e| e.to_string())?;
+        let decoder = Decoder::try_from(file).map_err(|e| e.to_string())?;
+        let duration = decoder.total_duration().map(|d| d.as_secs_f32());
+        let control = Arc::new(TrackControl {
+            gain    : AtomicU32::new(fade.value().to_bits()),
+            paused  : AtomicBool::new(false),
+            stopped : AtomicBool::new(false),
+        });
+        let playing = Arc::new(AtomicUsize::new(1));
+        let source_control = control.clone();
+        let buses = buses.clone();
+        let source = decoder
+            .pausable(false)
+            .stoppable()
+            .amplify(0.0)
+            .periodic_access(CONTROL_INTERVAL, move |source| {
+                let gain = f32::from_bits(source_control.gain.load(Ordering::Relaxed));
+                source.set_factor(gain * buses.gain(Bus::Music));
+                let stoppable = source.inner_mut();
+                if source_control.stopped.load(Ordering::Relaxed) {
+                    stoppable.stop();
+                }
+                stoppable.inner_mut().set_paused(source_control.paused.load(Ordering::Relaxed));
+            });
+        mixer.add(Done::new(source, playing.clone()));
+        Ok(Track { control, playing, fade, elapsed: 0.0, duration, pause_with_game })
+    }
+
+    fn finished(&self) -> bool {
+        self.playing.load(Ordering::Relaxed) == 0
+    }
+
+    fn stop(&self) {
+        self.control.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Advance fade and play time by `delta` real seconds (unless paused) and
+    /// publish gain/pause to the source. `game_rate` is the game time rate
+    /// (0 = paused), followed if the track pauses with the game.
+    fn update(&mut self, delta: f32, game_rate: f32) {
+        let factor = if self.pause_with_game { game_rate.clamp(0.0, 1.0) } else { 1.0 };
+        let paused = factor == 0.0;
+        if !paused {
+            self.fade.advance(delta);
+            self.elapsed += delta;
+        } else if self.fade.to == 0.0 {
+            // a fade-out runs on while paused, so the track ends instead of
+            // resuming with the game
+            self.fade.advance(delta);
+        }
+        self.control.gain.store((self.fade.value() * factor).to_bits(), Ordering::Relaxed);
+        self.control.paused.store(paused, Ordering::Relaxed);
+    }
+}
+
+/// Music player: streams tracks (res/music) on the music bus, from a playlist,
+/// with crossfades between tracks. Music can follow the game pause: its volume
+/// follows the game time rate and it pauses when time stops (e.g. in the menu).
+pub struct Music {
+    mixer           : Mixer,
+    buses           : Arc<Buses>,
+    playlist        : Playlist,
+    current         : Option<Track>,
+    /// Tracks replaced by a crossfade, fading out.
+    fading          : Vec<Track>,
+    /// Crossfade duration in seconds when the playlist advances.
+    crossfade       : f32,
+    /// Duck with the game time rate and pause with the game (tracks started from now on).
+    pause_with_game : bool,
+}
+
+impl Music {
+    pub fn new(mixer: &Mixer, buses: &Arc<Buses>) -> Self {
+        Music {
+            mixer           : mixer.clone(),
+            buses           : buses.clone(),
+            playlist        : Playlist::default(),
+            current         : None,
+            fading          : Vec::new(),
+            crossfade       : 0.0,
+            pause_with_game : true,
+        }
+    }
+
+    /// Play `filename` now, crossfading from the current track over `crossfade`
+    /// seconds. It replaces the playlist (repeating if `looping`).
+    pub fn play(&mut self, filename: &str, crossfade: f32, looping: bool) {
+        self.playlist.set(vec![ filename.to_string() ], looping);
+        self.crossfade = crossfade.max(0.0);
+        self.advance(self.crossfade);
+    }
+
+    /// Append a track to the playlist; starts it right away if nothing is playing.
+    pub fn queue(&mut self, filename: &str) {
+        self.playlist.push(filename.to_string());
+        if self.current.is_none() {
+            self.advance(0.0);
+        }
+    }
+
+    /// Repeat the playlist when it ends.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.playlist.looping = looping;
+    }
+
+    /// Whether the tracks started from now on duck and pause with the game
+    /// time (default: true). Tracks already playing or fading out keep theirs.
+    pub fn set_pause_with_game(&mut self, pause_with_game: bool) {
+        self.pause_with_game = pause_with_game;
+    }
+
+    /// Fade out the current track over `fade` seconds and clear the playlist.
+    pub fn stop(&mut self, fade: f32) {
+        self.playlist.clear();
+        self.fade_out_current(fade);
+    }
+
+    /// Update fades, pause state and playlist progress. `delta` is the real
+    /// time since the last update, `game_rate` the game time rate (0 = paused).
+    pub fn update(&mut self, delta: f32, game_rate: f32) {
+        for track in self.fading.iter_mut() {
+            track.update(delta, game_rate);
+        }
+        self.fading.retain(|track| {
+            let done = track.finished() || track.fade.finished();
+            if done {
+                track.stop();
+            }
+            !done
+        });
+
+        let mut advance = None;
+        if let Some(track) = &mut self.current {
+            track.update(delta, game_rate);
+            if track.finished() {
+                advance = Some(0.0);
+            } else if let Some(duration) = track.duration {
+                // start the next track early so it crossfades into the end of
+                // this one (the last track of the playlist plays to its end)
+                if self.crossfade > 0.0 && self.playlist.has_next() && track.elapsed >= duration - self.crossfade {
+                    advance = Some(self.crossfade);
+                }
+            }
+        }
+        if let Some(crossfade) = advance {
+            self.advance(crossfade);
+        }
+    }
+
+    /// Start the next playlist track, crossfading over `crossfade` seconds. The
+    /// current track fades out even if the playlist has ended.
+    fn advance(&mut self, crossfade: f32) {
+        self.fade_out_current(crossfade);
+        // try each track at most once, so a looping playlist of broken files ends
+        for _ in 0..self.playlist.tracks.len() {
+            let Some(filename) = self.playlist.next() else { return };
+            let fade = if crossfade > 0.0 { Fade::new(0.0, 1.0, crossfade) } else { Fade::new(1.0, 1.0, 0.0) };
+            match Track::start(&self.mixer, &self.buses, &filename, fade, self.pause_with_game) {
+                Ok(track) => {
+                    self.current = Some(track);
+                    return;
+                }
+                Err(e) => eprintln!("music: cannot play '{}': {}", filename, e),
+            }
+        }
+        if !self.playlist.tracks.is_empty() {
+            eprintln!("music: no playable track, stopping");
+            self.playlist.clear();
+        }
+    }
+
+    fn fade_out_current(&mut self, fade: f32) {
+        if let Some(mut track) = self.current.take() {
+            if fade > 0.0 {
+                track.fade = Fade::new(track.fade.value(), 0.0, fade);
+                self.fading.push(track);
+            } else {
+                track.stop();
+            }
+        }
+    }
+}
+
+impl Drop for Music {
+    /// Sources outlive the player on the shared mixer (e.g. on a level restart).
+    fn drop(&mut self) {
+        for track in self.current.iter().chain(self.fading.iter()) {
+            track.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fade, Playlist};
+
+    #[test]
+    fn fade_interpolates_and_finishes() {
+        let mut fade = Fade::new(0.0, 1.0, 2.0);
+        assert_eq!(fade.value(), 0.0);
+        fade.advance(0.5);
+        assert_eq!(fade.value(), 0.25);
+        fade.advance(5.0);
+        assert!(fade.finished());
+        assert_eq!(fade.value(), 1.0);
+        assert_eq!(Fade::new(1.0, 0.0, 0.0).value(), 0.0);
+    }
+
+    #[test]
+    fn playlist_plays_in_order_and_loops() {
+        let mut playlist = Playlist::default();
+        playlist.set(vec![ "a".to_string(), "b".to_string() ], false);
+        assert_eq!(playlist.next().as_deref(), Some("a"));
+        assert!(playlist.has_next());
+        assert_eq!(playlist.next().as_deref(), Some("b"));
+        assert!(!playlist.has_next());
+        assert_eq!(playlist.next(), None);
+        playlist.looping = true;
+        assert!(playlist.has_next());
+        assert_eq!(playlist.next().as_deref(), Some("a"));
+        playlist.push("c".to_string());
+        assert_eq!(playlist.next().as_deref(), Some("b"));
+        assert_eq!(playlist.next().as_deref(), Some("c"));
+        playlist.clear();
+        assert_eq!(playlist.next(), None);
+    }
+}
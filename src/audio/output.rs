@@ -0,0 +1,242 @@
+use crate::prelude::*;
+use rodio::{DeviceSinkBuilder, MixerDeviceSink};
+use rodio::mixer::{self, Mixer, MixerSource};
+use std::num::NonZero;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+
+/// Environment variable selecting the audio output: "device" (default),
+/// "null" (silent) or "wav:<file>" (mix into a WAV file).
+pub const ENV_VAR: &str = "SPACEGAME_AUDIO";
+
+/// Channels and sample rate of the fallback outputs.
+const CHANNELS: u16 = 2;
+const SAMPLE_RATE: u32 = 44100;
+
+/// Mixing interval of the fallback outputs.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Where the mixed audio goes.
+enum Backend {
+    /// Sound device (played while open).
+    Device { _sink: MixerDeviceSink },
+    /// Mixed in real time by a thread that discards or records the samples.
+    Drain {
+        stop    : Arc<AtomicBool>,
+        thread  : Option<JoinHandle<()>>,
+    },
+    /// Mixed and recorded on `Output::render` only (offline rendering).
+    Manual {
+        source  : MixerSource,
+        recorder: Option<Box<dyn Recorder>>,
+        /// Sample frames rendered so far.
+        frames  : u64,
+        /// Time rendered so far.
+        rendered: Duration,
+    },
+}
+
+/// Audio output: the mixer all sounds are played on (see `Audio`), backed by
+/// the sound device, or without one by a silent output or a WAV file.
+pub struct Output {
+    mixer   : Mixer,
+    backend : Backend,
+    name    : String,
+}
+
+impl Output {
+    /// The output selected by SPACEGAME_AUDIO, falling back to the silent
+    /// output if it cannot be opened (e.g. no sound device).
+    pub fn open() -> Output {
+        let selected = std::env::var(ENV_VAR).unwrap_or_default();
+        let result = match selected.as_str() {
+            "" | "device" => Self::device(),
+            "null" => Ok(Self::null()),
+            other => match other.strip_prefix("wav:") {
+                Some(path) => Self::wav(path),
+                None => Err(format!("unknown {} value '{}'", ENV_VAR, other)),
+            },
+        };
+        result.unwrap_or_else(|e| {
+            eprintln!("audio: {}, falling back to silent output", e);
+            Self::null()
+        })
+    }
+
+    /// Output to the default sound device.
+    pub fn device() -> Result<Output, String> {
+        let mut sink = DeviceSinkBuilder::open_default_sink().map_err(|e| format!("cannot open sound device: {}", e))?;
+        sink.log_on_drop(false);
+        Ok(Output { mixer: sink.mixer().clone(), backend: Backend::Device { _sink: sink }, name: "device".to_string() })
+    }
+
+    /// Silent output: sounds are mixed and discarded in real time, so they
+    /// play and finish as with a device.
+    pub fn null() -> Output {
+        Self::drain("null".to_string(), Box::new(()))
+    }
+
+    /// Output mixed into a WAV file (32 bit float, stereo) in real time.
+    pub fn wav(path: &str) -> Result<Output, String> {
+        Ok(Self::drain(format!("wav:{}", path), Self::wav_writer(path)?))
+    }
+
+    /// Output mixed into a WAV file as `render` is called, independent of
+    /// real time (offline rendering).
+    pub fn wav_offline(path: &str) -> Result<Output, String> {
+        let (mixer, source) = mixer::mixer(NonZero::new(CHANNELS).unwrap(), NonZero::new(SAMPLE_RATE).unwrap());
+        let recorder = Some(Self::wav_writer(path)?);
+        Ok(Output { mixer, backend: Backend::Manual { source, recorder, frames: 0, rendered: Duration::ZERO }, name: format!("offline:{}", path) })
+    }
+
+    fn wav_writer(path: &str) -> Result<Box<dyn Recorder>, String> {
+        let spec = hound::WavSpec {
+            channels        : CHANNELS,
+            sample_rate     : SAMPLE_RATE,
+            bits_per_sample : 32,
+            sample_format   : hound::SampleFormat::Float,
+        };
+        let writer = hound::WavWriter::create(path, spec).map_err(|e| format!("cannot create '{}': {}", path, e))?;
+        Ok(Box::new(writer))
+    }
+
+    /// Output mixed by a thread that passes each interval's samples to `recorder`.
+    fn drain(name: String, mut recorder: Box<dyn Recorder>) -> Output {
+        let (mixer, mut source) = mixer::mixer(NonZero::new(CHANNELS).unwrap(), NonZero::new(SAMPLE_RATE).unwrap());
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread_name = name.clone();
+        let thread = std::thread::spawn(move || {
+            let mut next = Instant::now();
+            let mut samples = Vec::new();
+            let count = (SAMPLE_RATE as u128 * DRAIN_INTERVAL.as_millis() / 1000) as usize;
+            while !thread_stop.load(Ordering::Relaxed) {
+                mix(&mut source, count, &mut samples);
+                if let Err(e) = recorder.write(&samples) {
+                    eprintln!("audio: {} output failed: {}", thread_name, e);
+                    break;
+                }
+                next += DRAIN_INTERVAL;
+                std::thread::sleep(next.saturating_duration_since(Instant::now()));
+            }
+            if let Err(e) = recorder.finish() {
+                eprintln!("audio: {} output failed: {}", thread_name, e);
+            }
+        });
+        Output { mixer, backend: Backend::Drain { stop, thread: Some(thread) }, name }
+    }
+
+    /// Mix and record the next `duration` of audio (offline output only; the
+    /// other outputs mix in real time).
+    pub fn render(&mut self, duration: Duration) -> Result<(), String> {
+        let Backend::Manual { source, recorder: Some(recorder), frames, rendered } = &mut self.backend else {
+            return Ok(());
+        };
+        // whole sample frames up to the total time, so no rounding drift accumulates
+        *rendered += duration;
+        let total = (rendered.as_secs_f64() * SAMPLE_RATE as f64).round() as u64;
+        let mut samples = Vec::new();
+        mix(source, (total - *frames) as usize, &mut samples);
+        *frames = total;
+        recorder.write(&samples)
+    }
+
+    /// The mixer to play sounds on.
+    pub fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    /// Output name ("device", "null" or "wav:<file>").
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for Output {
+    /// Stops the mixing thread and completes the WAV file.
+    fn drop(&mut self) {
+        match &mut self.backend {
+            Backend::Drain { stop, thread } => {
+                stop.store(true, Ordering::Relaxed);
+                if let Some(thread) = thread.take() {
+                    let _ = thread.join();
+                }
+            }
+            Backend::Manual { recorder, .. } => {
+                if let Some(Err(e)) = recorder.take().map(|recorder| recorder.finish()) {
+                    eprintln!("audio: {} output failed: {}", self.name, e);
+                }
+            }
+            Backend::Device { .. } => { }
+        }
+    }
+}
+
+/// Receives the samples mixed by a drained output.
+trait Recorder: Send {
+    fn write(&mut self, samples: &[rodio::Sample]) -> Result<(), String>;
+    /// Complete the recording (output dropped).
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+/// Discards the samples (silent output).
+impl Recorder for () {
+    fn write(&mut self, _samples: &[rodio::Sample]) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl Recorder for hound::WavWriter<std::io::BufWriter<fs::File>> {
+    fn write(&mut self, samples: &[rodio::Sample]) -> Result<(), String> {
+        samples.iter().try_for_each(|&sample| self.write_sample(sample)).map_err(|e| e.to_string())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        self.finalize().map_err(|e| e.to_string())
+    }
+}
+
+/// Mix `frames` sample frames from `source` into `samples`.
+fn mix(source: &mut MixerSource, frames: usize, samples: &mut Vec<rodio::Sample>) {
+    samples.clear();
+    samples.extend(source.by_ref().take(frames * CHANNELS as usize));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::Sound;
+
+    #[test]
+    fn wav_output_records_played_sounds() {
+        let path = std::env::temp_dir().join(format!("spacegame-output-{}.wav", std::process::id()));
+        let output = Output::wav(path.to_str().unwrap()).unwrap();
+        output.mixer().add(Sound::load("res/sound/projectile/pew2.ogg").unwrap().source());
+        std::thread::sleep(Duration::from_millis(100));
+        drop(output);
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, CHANNELS);
+        assert!(reader.duration() > 0);
+        let samples: Vec<f32> = reader.into_samples().map(|sample| sample.unwrap()).collect();
+        assert!(samples.iter().any(|&sample| sample != 0.0));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn offline_output_records_rendered_time() {
+        let path = std::env::temp_dir().join(format!("spacegame-offline-{}.wav", std::process::id()));
+        let mut output = Output::wav_offline(path.to_str().unwrap()).unwrap();
+        output.mixer().add(Sound::load("res/sound/projectile/pew2.ogg").unwrap().source());
+        for _ in 0..30 {
+            output.render(Duration::from_secs_f64(1.0 / 60.0)).unwrap();
+        }
+        drop(output);
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), SAMPLE_RATE / 2);
+        let _ = fs::remove_file(&path);
+    }
+}
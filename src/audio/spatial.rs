@@ -0,0 +1,71 @@
+use crate::prelude::*;
+
+/// Maximum pan: sounds at or beyond the screen edge keep this much of the far
+/// channel (hard panning sounds unnatural on headphones).
+const PAN_WIDTH: f32 = 0.8;
+
+/// Distance (in screen widths) within which positional sounds play at full volume.
+const NEAR: f32 = 0.25;
+
+/// Distance (in screen widths) beyond which positional sounds are silent.
+const FAR: f32 = 1.5;
+
+/// Where positional sounds are heard from: a point in screen pixels (screen
+/// center unless the script moves it, e.g. with a camera) and the screen width,
+/// which scales panning and attenuation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Listener {
+    pub position: Vec2,
+    pub width   : f32,
+}
+
+impl Listener {
+    /// Stereo channel gains (left, right) of a sound at `position`: panned by
+    /// its horizontal offset (full pan at half a screen width) with a balance
+    /// law (centered = both channels at 1, like a non-positional sound; panning
+    /// right attenuates the left channel and vice versa), and attenuated
+    /// linearly from NEAR to FAR.
+    pub fn gains(&self, position: Vec2) -> (f32, f32) {
+        let width = self.width.max(1.0);
+        let offset = position - self.position;
+        let pan = (offset.0 / (width * 0.5)).clamp(-1.0, 1.0) * PAN_WIDTH;
+        let distance = offset.len() / width;
+        let attenuation = (1.0 - (distance - NEAR) / (FAR - NEAR)).clamp(0.0, 1.0);
+        ((1.0 - pan.max(0.0)) * attenuation, (1.0 + pan.min(0.0)) * attenuation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener() -> Listener {
+        Listener { position: Vec2(640.0, 360.0), width: 1280.0 }
+    }
+
+    #[test]
+    fn center_plays_at_full_volume() {
+        assert_eq!(listener().gains(Vec2(640.0, 360.0)), (1.0, 1.0));
+        assert_eq!(listener().gains(Vec2(700.0, 400.0)).1, 1.0);
+    }
+
+    #[test]
+    fn pans_toward_the_sound_side() {
+        let (left, right) = listener().gains(Vec2(200.0, 360.0));
+        assert!(left > right);
+        let (left, right) = listener().gains(Vec2(1080.0, 360.0));
+        assert!(right > left);
+        // never fully hard panned
+        let (left, right) = listener().gains(Vec2(1280.0, 360.0));
+        assert!((left / right - 0.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn attenuates_with_distance() {
+        let near = listener().gains(Vec2(640.0, 500.0));
+        let mid = listener().gains(Vec2(640.0, 1000.0));
+        let far = listener().gains(Vec2(640.0, 360.0 + 1280.0 * 1.5));
+        assert!(near.0 > mid.0 && mid.0 > 0.0);
+        assert_eq!(far, (0.0, 0.0));
+    }
+}
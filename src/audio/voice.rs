@@ -0,0 +1,223 @@
+use crate::prelude::*;
+use super::Listener;
+use rodio::{Source, Sample, ChannelCount, SampleRate};
+use rodio::mixer::Mixer;
+use rodio::source::Done;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+/// Interval at which playing sources pick up volume, pan and speed changes.
+pub const CONTROL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Where a voice is heard from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    /// Not positional: centered, full volume.
+    Center,
+    /// Fixed position in screen pixels.
+    At(Vec2),
+    /// Follows an entity (ID shared with Itsy); `Vec2` is its last known position.
+    Entity(u64, Vec2),
+}
+
+/// Gain and playback of a voice, polled while it plays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mix {
+    /// Bus gain.
+    pub gain    : f32,
+    /// Playback speed (1 = normal), None = paused.
+    pub speed   : Option<f32>,
+}
+
+/// Channel gains shared between a `Voice` and its playing source.
+struct VoiceControl {
+    /// Left/right gain (f32 bits), multiplied by the bus gain.
+    left    : AtomicU32,
+    right   : AtomicU32,
+    stopped : AtomicBool,
+}
+
+/// A playing sound effect.
+pub struct Voice {
+    control     : Arc<VoiceControl>,
+    /// 1 while the source is playing, 0 once it finished.
+    playing     : Arc<AtomicUsize>,
+    pub placement: Placement,
+    pub filename: String,
+    /// Voice limit group (see `Limits::group_of`).
+    pub group   : String,
+    pub started : Instant,
+}
+
+impl Voice {
+    /// Start playing `source` (the sound `filename` of `group`) with the gain
+    /// and speed polled from `mix`, panned/attenuated by `listener` (see `update`).
+    pub fn start<S, M>(mixer: &Mixer, mix: M, source: S, filename: &str, group: String, placement: Placement, listener: &Listener) -> Voice
+        where S: Source + Send + 'static, M: Fn() -> Mix + Send + 'static
+    {
+        let (left, right) = gains(placement, listener);
+        let control = Arc::new(VoiceControl {
+            left    : AtomicU32::new(left.to_bits()),
+            right   : AtomicU32::new(right.to_bits()),
+            stopped : AtomicBool::new(false),
+        });
+        let playing = Arc::new(AtomicUsize::new(1));
+        let source_control = control.clone();
+        let initial = mix();
+        let source = Pan::new(source, left, right)
+            .speed(initial.speed.unwrap_or(1.0))
+            .pausable(initial.speed.is_none())
+            .stoppable()
+            .amplify(initial.gain)
+            .periodic_access(CONTROL_INTERVAL, move |source| {
+                let mix = mix();
+                source.set_factor(mix.gain);
+                let stoppable = source.inner_mut();
+                if source_control.stopped.load(Ordering::Relaxed) {
+                    stoppable.stop();
+                }
+                let pausable = stoppable.inner_mut();
+                pausable.set_paused(mix.speed.is_none());
+                let speed = pausable.inner_mut();
+                if let Some(factor) = mix.speed {
+                    speed.set_factor(factor);
+                }
+                speed.inner_mut().set_gains(
+                    f32::from_bits(source_control.left.load(Ordering::Relaxed)),
+                    f32::from_bits(source_control.right.load(Ordering::Relaxed)),
+                );
+            });
+        mixer.add(Done::new(source, playing.clone()));
+        Voice {
+            control     : control,
+            playing     : playing,
+            placement   : placement,
+            filename    : filename.to_string(),
+            group       : group,
+            started     : Instant::now(),
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.playing.load(Ordering::Relaxed) == 0
+    }
+
+    /// Stop the voice (within CONTROL_INTERVAL).
+    pub fn stop(&self) {
+        self.control.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Move a voice following an entity to the entity's current position
+    /// (None = entity gone: keep the last position) and update its gains.
+    pub fn update(&mut self, listener: &Listener, position_of: &impl Fn(u64) -> Option<Vec2>) {
+        if let Placement::Entity(id, position) = &mut self.placement {
+            if let Some(current) = position_of(*id) {
+                *position = current;
+            }
+        }
+        let (left, right) = gains(self.placement, listener);
+        self.control.left.store(left.to_bits(), Ordering::Relaxed);
+        self.control.right.store(right.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Channel gains of a placement.
+fn gains(placement: Placement, listener: &Listener) -> (f32, f32) {
+    match placement {
+        Placement::Center => (1.0, 1.0),
+        Placement::At(position) | Placement::Entity(_, position) => listener.gains(position),
+    }
+}
+
+/// Source scaling the left and right channel by separate gains (panning).
+/// Stereo sources stay stereo (further channels alternate between the left
+/// and right gain); mono sources are played on both channels.
+struct Pan<S> {
+    input   : S,
+    /// Left and right gain.
+    gains   : [f32; 2],
+    /// Channel of the next sample of a multichannel input.
+    channel : u16,
+    /// Mono input: the current sample, still to be played on the right channel.
+    right   : Option<Sample>,
+}
+
+impl<S: Source> Pan<S> {
+    fn new(input: S, left: f32, right: f32) -> Self {
+        Pan { input: input, gains: [ left, right ], channel: 0, right: None }
+    }
+
+    fn set_gains(&mut self, left: f32, right: f32) {
+        self.gains = [ left, right ];
+    }
+
+    fn is_mono(&self) -> bool {
+        self.input.channels().get() == 1
+    }
+}
+
+impl<S: Source> Iterator for Pan<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if let Some(sample) = self.right.take() {
+            return Some(sample * self.gains[1]);
+        }
+        let mono = self.is_mono();
+        let channels = self.input.channels().get();
+        let sample = self.input.next()?;
+        if mono {
+            self.right = Some(sample);
+            return Some(sample * self.gains[0]);
+        }
+        let gain = self.gains[(self.channel % 2) as usize];
+        self.channel = (self.channel + 1) % channels;
+        Some(sample * gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let factor = if self.is_mono() { 2 } else { 1 };
+        let (lower, upper) = self.input.size_hint();
+        (lower * factor, upper.map(|upper| upper * factor))
+    }
+}
+
+impl<S: Source> Source for Pan<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        let factor = if self.is_mono() { 2 } else { 1 };
+        self.input.current_span_len().map(|len| len * factor)
+    }
+
+    fn channels(&self) -> ChannelCount {
+        if self.is_mono() { ChannelCount::new(2).unwrap() } else { self.input.channels() }
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pan;
+    use rodio::{Source, ChannelCount, SampleRate};
+    use rodio::buffer::SamplesBuffer;
+
+    fn buffer(channels: u16, samples: Vec<f32>) -> SamplesBuffer {
+        SamplesBuffer::new(ChannelCount::new(channels).unwrap(), SampleRate::new(44100).unwrap(), samples)
+    }
+
+    #[test]
+    fn pans_without_downmixing() {
+        let stereo = Pan::new(buffer(2, vec![ 1.0, 0.5, 1.0, 0.5 ]), 0.5, 1.0);
+        assert_eq!(stereo.channels().get(), 2);
+        assert_eq!(stereo.collect::<Vec<_>>(), vec![ 0.5, 0.5, 0.5, 0.5 ]);
+
+        let mono = Pan::new(buffer(1, vec![ 1.0, 0.5 ]), 1.0, 0.0);
+        assert_eq!(mono.channels().get(), 2);
+        assert_eq!(mono.collect::<Vec<_>>(), vec![ 1.0, 0.0, 0.5, 0.0 ]);
+    }
+}
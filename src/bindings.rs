@@ -0,0 +1,153 @@
+use crate::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// Default bindings shipped with the game.
+pub const DEFAULT_PATH: &str = "res/config/bindings.toml";
+
+/// Bindings changed by the player (options menu); override the defaults per action.
+pub const USER_PATH: &str = "user/bindings.toml";
+
+/// Inputs that can be bound by pressing them while an action is being rebound.
+pub const BINDABLE: &[InputId] = &[
+    InputId::A, InputId::B, InputId::C, InputId::D, InputId::E, InputId::F, InputId::G,
+    InputId::H, InputId::I, InputId::J, InputId::K, InputId::L, InputId::M, InputId::N,
+    InputId::O, InputId::P, InputId::Q, InputId::R, InputId::S, InputId::T, InputId::U,
+    InputId::V, InputId::W, InputId::X, InputId::Y, InputId::Z,
+    InputId::Key1, InputId::Key2, InputId::Key3, InputId::Key4, InputId::Key5,
+    InputId::Key6, InputId::Key7, InputId::Key8, InputId::Key9, InputId::Key0,
+    InputId::F1, InputId::F2, InputId::F3, InputId::F4, InputId::F5, InputId::F6,
+    InputId::F7, InputId::F8, InputId::F9, InputId::F10, InputId::F11, InputId::F12,
+    InputId::Space, InputId::Return, InputId::Tab, InputId::Backspace,
+    InputId::CursorUp, InputId::CursorDown, InputId::CursorLeft, InputId::CursorRight,
+    InputId::LShift, InputId::RShift, InputId::LControl, InputId::RControl, InputId::LAlt, InputId::RAlt,
+    InputId::Mouse1, InputId::Mouse2, InputId::Mouse3, InputId::Mouse4, InputId::Mouse5,
+];
+
+/// Named input actions, each bound to one or more keys or mouse buttons.
+/// The Itsy script queries actions by name instead of raw keys.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    /// Action name -> inputs triggering it (any of them).
+    pub actions: BTreeMap<String, Vec<InputId>>,
+}
+
+impl Bindings {
+    /// Loads the default bindings, overridden per action by the player's bindings (if any).
+    pub fn load() -> Bindings {
+        let mut bindings = Self::load_file(DEFAULT_PATH).unwrap_or_else(|e| {
+            eprintln!("bindings: cannot load defaults '{}': {}", DEFAULT_PATH, e);
+            Bindings::default()
+        });
+        if Path::new(USER_PATH).is_file() {
+            match Self::load_file(USER_PATH) {
+                Ok(user) => bindings.merge(user),
+                Err(e) => eprintln!("bindings: ignoring '{}': {}", USER_PATH, e),
+            }
+        }
+        bindings
+    }
+
+    /// Loads bindings from a TOML file.
+    pub fn load_file(path: &str) -> Result<Bindings, String> {
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&source)
+    }
+
+    /// Parses bindings from TOML source.
+    pub fn parse(source: &str) -> Result<Bindings, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Saves the actions bound differently than in the defaults as the player's
+    /// bindings (USER_PATH); the others keep following the defaults.
+    pub fn save(&self) -> io::Result<()> {
+        let defaults = Self::load_file(DEFAULT_PATH).map_err(io::Error::other)?;
+        let source = toml::to_string_pretty(&self.changes_from(&defaults)).map_err(io::Error::other)?;
+        if let Some(dir) = Path::new(USER_PATH).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(USER_PATH, source)
+    }
+
+    /// The actions bound differently than in `defaults`.
+    pub fn changes_from(&self, defaults: &Bindings) -> Bindings {
+        Bindings {
+            actions: self.actions.iter()
+                .filter(|&(action, inputs)| defaults.actions.get(action) != Some(inputs))
+                .map(|(action, inputs)| (action.clone(), inputs.clone()))
+                .collect(),
+        }
+    }
+
+    /// Replaces the bindings of all actions listed in `other`.
+    pub fn merge(&mut self, other: Bindings) {
+        self.actions.extend(other.actions);
+    }
+
+    /// Inputs bound to `action` (empty for unknown actions).
+    pub fn inputs(&self, action: &str) -> &[InputId] {
+        self.actions.get(action).map(|inputs| inputs.as_slice()).unwrap_or(&[])
+    }
+
+    /// Binds `action` to the given inputs only. Unknown actions are ignored.
+    pub fn bind(&mut self, action: &str, inputs: Vec<InputId>) -> bool {
+        match self.actions.get_mut(action) {
+            Some(bound) => { *bound = inputs; true }
+            None => false,
+        }
+    }
+
+    /// Human readable list of the inputs bound to `action`, e.g. "Mouse1 / LControl".
+    pub fn describe(&self, action: &str) -> String {
+        let names: Vec<String> = self.inputs(action).iter().map(|input| format!("{:?}", input)).collect();
+        if names.is_empty() { "-".to_string() } else { names.join(" / ") }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_parse() {
+        let bindings = Bindings::load_file(DEFAULT_PATH).unwrap();
+        assert_eq!(bindings.inputs("fire"), &[InputId::Mouse1, InputId::LControl]);
+        assert_eq!(bindings.describe("menu_select"), "Return");
+        assert!(bindings.inputs("no_such_action").is_empty());
+    }
+
+    #[test]
+    fn user_bindings_override_per_action() {
+        let mut bindings = Bindings::parse("[actions]\nfire = [\"Mouse1\"]\nstrafe = [\"RShift\"]\n").unwrap();
+        bindings.merge(Bindings::parse("[actions]\nfire = [\"Space\"]\n").unwrap());
+        assert_eq!(bindings.inputs("fire"), &[InputId::Space]);
+        assert_eq!(bindings.inputs("strafe"), &[InputId::RShift]);
+    }
+
+    #[test]
+    fn bind_replaces_known_actions_only() {
+        let mut bindings = Bindings::parse("[actions]\nfire = [\"Mouse1\", \"LControl\"]\n").unwrap();
+        assert!(bindings.bind("fire", vec![InputId::F]));
+        assert!(!bindings.bind("jump", vec![InputId::Space]));
+        assert_eq!(bindings.describe("fire"), "F");
+        let saved = toml::to_string_pretty(&bindings).unwrap();
+        assert_eq!(Bindings::parse(&saved).unwrap(), bindings);
+    }
+
+    #[test]
+    fn changes_keep_only_rebound_actions() {
+        let defaults = Bindings::load_file(DEFAULT_PATH).unwrap();
+        let mut bindings = defaults.clone();
+        assert!(bindings.changes_from(&defaults).actions.is_empty());
+        bindings.bind("fire", vec![InputId::F]);
+        let changes = bindings.changes_from(&defaults);
+        assert_eq!(changes.actions.keys().collect::<Vec<_>>(), vec!["fire"]);
+        let saved = toml::to_string_pretty(&changes).unwrap();
+        let mut loaded = defaults.clone();
+        loaded.merge(Bindings::parse(&saved).unwrap());
+        assert_eq!(loaded, bindings);
+    }
+}
@@ -1,13 +1,55 @@
 use radiant::*;
 use std::sync::Mutex;
 
+/// Maximum number of downsampled levels (maximum spread).
+pub const MAX_SPREAD: u8 = 8;
+
+/// Maximum blur kernel radius in taps.
+pub const MAX_RADIUS: u32 = 8;
+
+/// Levels stop before either side gets smaller than this (pixels).
+const MIN_LEVEL_SIZE: u32 = 16;
+
+/// Standard deviation (in taps) of the radius 1 kernel: gives the original
+/// 0.3125 / 0.375 / 0.3125 weights. Wider kernels use radius / 2 when larger.
+const MIN_SIGMA: f32 = 1.656;
+
+/// Glare parameters. The defaults extract everything (threshold 0) and clamp
+/// the result to 1, like the original glare.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BloomParams {
+    /// Blur passes.
+    pub iterations  : u32,
+    /// Number of downsampled levels blurred and combined (1..=MAX_SPREAD,
+    /// limited to the levels the resolution allows).
+    pub spread      : u8,
+    /// Brightness of the combined levels.
+    pub brightness  : f32,
+    /// Brightness (max color channel) below which pixels do not glow.
+    pub threshold   : f32,
+    /// Width of the soft transition around the threshold.
+    pub knee        : f32,
+    /// Blur kernel radius in taps (1..=MAX_RADIUS).
+    pub radius      : u32,
+    /// Maximum output value per channel.
+    pub clamp       : f32,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        BloomParams { iterations: 2, spread: 5, brightness: 5.0, threshold: 0.0, knee: 0.0, radius: 1, clamp: 1.0 }
+    }
+}
+
 pub struct Bloom {
-    targets         : [[Texture; 5]; 2],
+    /// Unprocessed input (half resolution).
+    input           : Texture,
+    /// Downsampled levels (input size / 1, 2, 4, ...) and their blur buffers.
+    targets         : [Vec<Texture>; 2],
+    threshold_program: Mutex<Program>,
     blur_program    : Mutex<Program>,
     combine_program : Mutex<Program>,
-    iterations      : u32,
-    spread          : u8,
-    brightness      : f32,
+    params          : BloomParams,
 }
 
 impl Postprocessor for Bloom {
@@ -15,15 +57,22 @@ impl Postprocessor for Bloom {
 
     /// Returns the target where the postprocessor expects the unprocessed input.
     fn target(self: &Self) -> &Texture {
-        &self.targets[0][0]
+        &self.input
     }
 
     /// Process received data.
     fn process(self: &Self, renderer: &Renderer, _: &Self::T) {
         use std::ops::DerefMut;
+        let spread = self.spread();
+
+        // Extract the bright parts into the first level
+        let threshold = self.threshold_program.lock().unwrap();
+        renderer.render_to(&self.targets[0][0], || {
+            renderer.fill().blendmode(blendmodes::COPY).program(&threshold).texture(&self.input).draw();
+        });
 
         // Copy to progressively smaller textures
-        for i in 1..self.spread as usize {
+        for i in 1..spread {
             renderer.render_to(&self.targets[0][i], || {
                 renderer.copy_from(&self.targets[0][i-1], TextureFilter::Linear);
             });
@@ -32,11 +81,11 @@ impl Postprocessor for Bloom {
         let mut blur = self.blur_program.lock().unwrap();
         let blur = blur.deref_mut();
 
-        for _ in 0..self.iterations {
+        for _ in 0..self.params.iterations {
 
             // Apply horizontal blur
             blur.set_uniform("horizontal", &true);
-            for i in 0..self.spread as usize {
+            for i in 0..spread {
                 renderer.render_to(&self.targets[1][i], || {
                     renderer.fill().blendmode(blendmodes::ALPHA).program(&blur).texture(&self.targets[0][i]).draw();
                 });
@@ -44,12 +93,19 @@ impl Postprocessor for Bloom {
 
             // Apply vertical blur
             blur.set_uniform("horizontal", &false);
-            for i in 0..self.spread as usize {
+            for i in 0..spread {
                 renderer.render_to(&self.targets[0][i], || {
                     renderer.fill().blendmode(blendmodes::ALPHA).program(&blur).texture(&self.targets[1][i]).draw();
                 });
             }
         }
+
+        // Accumulate the levels into the first one, smallest first
+        for i in (1..spread).rev() {
+            renderer.render_to(&self.targets[0][i-1], || {
+                renderer.fill().blendmode(blendmodes::ADD).texture(&self.targets[0][i]).draw();
+            });
+        }
     }
 
     /// Draw processed input. The renderer has already set the correct target.
@@ -57,67 +113,115 @@ impl Postprocessor for Bloom {
         use std::ops::DerefMut;
         let mut combine = self.combine_program.lock().unwrap();
         let combine = combine.deref_mut();
-        renderer.fill().blendmode(*blendmode).program(&combine).draw();
-        self.targets[0][0].clear(Color::TRANSPARENT);
+        renderer.fill().blendmode(*blendmode).program(&combine).texture(&self.targets[0][0]).draw();
+        self.input.clear(Color::TRANSPARENT);
     }
 }
 
 impl Bloom {
-    pub fn new(context: &Context, dimensions: (u32, u32), iterations: u32, spread: u8, brightness: f32) -> Self {
-        use std::cmp::min;
-
-        let blur_program = Program::from_string(&context, include_str!("../res/shader/blur.wgsl")).unwrap();
-        let mut combine_program = Program::from_string(&context, include_str!("../res/shader/combine.wgsl")).unwrap();
-        let targets = Self::create_targets(context, dimensions);
-
-        let spread = min(targets[0].len() as u8, spread);
-
-        Self::set_sample_uniforms(&mut combine_program, &targets, brightness, spread);
-
-        Bloom {
+    pub fn new(context: &Context, dimensions: (u32, u32), params: BloomParams) -> Self {
+        let threshold_program = Program::from_string(&context, include_str!("../res/shader/threshold.wgsl")).unwrap();
+        let mut blur_program = Program::from_string(&context, include_str!("../res/shader/blur.wgsl")).unwrap();
+        let combine_program = Program::from_string(&context, include_str!("../res/shader/combine.wgsl")).unwrap();
+        // first set, so it maps to _rd_flags.x (see blur.wgsl)
+        blur_program.set_uniform("horizontal", &true);
+
+        let bloom = Bloom {
+            input           : Self::create_input(context, dimensions),
+            targets         : Self::create_targets(context, dimensions),
+            threshold_program: Mutex::new(threshold_program),
             blur_program    : Mutex::new(blur_program),
             combine_program : Mutex::new(combine_program),
-            iterations      : iterations,
-            targets         : targets,
-            spread          : spread,
-            brightness      : brightness,
-        }
+            params          : params,
+        };
+        bloom.set_uniforms();
+        bloom
+    }
+
+    /// Replace the parameters; applies from the next frame.
+    pub fn set_params(&mut self, params: BloomParams) {
+        self.params = params;
+        self.set_uniforms();
     }
 
     /// Rebuild the internal render targets for a new display size (display resize).
     pub fn rebuild(&mut self, context: &Context, dimensions: (u32, u32)) {
-        let targets = Self::create_targets(context, dimensions);
-        let mut combine_program = self.combine_program.lock().unwrap();
-        Self::set_sample_uniforms(&mut combine_program, &targets, self.brightness, self.spread);
-        self.targets = targets;
+        self.input = Self::create_input(context, dimensions);
+        self.targets = Self::create_targets(context, dimensions);
+        self.set_uniforms();
     }
 
-    /// Create the pyramid of downsampled textures (full res / 2 / 4 / 8 / 16 / 32).
-    fn create_targets(context: &Context, dimensions: (u32, u32)) -> [[Texture; 5]; 2] {
+    /// Number of levels in use: the spread, limited to the existing levels.
+    fn spread(&self) -> usize {
+        (self.params.spread.max(1) as usize).min(self.targets[0].len())
+    }
+
+    fn set_uniforms(&self) {
+        let params = &self.params;
+        let mut threshold = self.threshold_program.lock().unwrap();
+        threshold.set_uniform("threshold", &params.threshold.max(0.0));
+        threshold.set_uniform("knee", &params.knee.max(0.0));
+        let radius = params.radius.clamp(1, MAX_RADIUS);
+        let mut blur = self.blur_program.lock().unwrap();
+        blur.set_uniform("radius", &(radius as f32));
+        blur.set_uniform("sigma", &kernel_sigma(radius));
+        let mut combine = self.combine_program.lock().unwrap();
+        combine.set_uniform("brightness", &(params.brightness / self.spread() as f32));
+        combine.set_uniform("clamp", &params.clamp);
+    }
+
+    fn create_input(context: &Context, dimensions: (u32, u32)) -> Texture {
+        let (width, height) = dimensions;
+        Texture::builder(context).format(TextureFormat::F16F16F16F16).dimensions((width / 2, height / 2)).build().unwrap()
+    }
+
+    /// Create the pyramid of downsampled textures (full res / 2 / 4 / 8 / ...,
+    /// see `level_count`).
+    fn create_targets(context: &Context, dimensions: (u32, u32)) -> [Vec<Texture>; 2] {
         let (width, height) = dimensions;
         let builder = Texture::builder(context).format(TextureFormat::F16F16F16F16);
+        let create = || -> Vec<Texture> {
+            (1..=level_count(dimensions)).map(|i| {
+                builder.clone().dimensions(((width >> i).max(1), (height >> i).max(1))).build().unwrap()
+            }).collect()
+        };
+        [ create(), create() ]
+    }
+}
+
+/// Number of pyramid levels for a display of size `dimensions`: halving from
+/// half resolution until a side would drop below MIN_LEVEL_SIZE (at least 1,
+/// at most MAX_SPREAD).
+fn level_count(dimensions: (u32, u32)) -> usize {
+    let smaller = dimensions.0.min(dimensions.1);
+    (1..=MAX_SPREAD as u32).take_while(|&i| (smaller >> i) >= MIN_LEVEL_SIZE).count().max(1)
+}
+
+/// Standard deviation of the blur kernel with `radius` taps per side.
+fn kernel_sigma(radius: u32) -> f32 {
+    (radius as f32 * 0.5).max(MIN_SIGMA)
+}
 
-        [ [
-            builder.clone().dimensions((width / 2, height / 2)).build().unwrap(),
-            builder.clone().dimensions((width / 4, height / 4)).build().unwrap(),
-            builder.clone().dimensions((width / 8, height / 8)).build().unwrap(),
-            builder.clone().dimensions((width / 16, height / 16)).build().unwrap(),
-            builder.clone().dimensions((width / 32, height / 32)).build().unwrap(),
-        ], [
-            builder.clone().dimensions((width / 2, height / 2)).build().unwrap(),
-            builder.clone().dimensions((width / 4, height / 4)).build().unwrap(),
-            builder.clone().dimensions((width / 8, height / 8)).build().unwrap(),
-            builder.clone().dimensions((width / 16, height / 16)).build().unwrap(),
-            builder.clone().dimensions((width / 32, height / 32)).build().unwrap(),
-        ] ]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_follow_resolution() {
+        assert_eq!(level_count((1920, 1080)), 6);
+        assert_eq!(level_count((1280, 720)), 5);
+        assert_eq!(level_count((3840, 2160)), 7);
+        assert_eq!(level_count((20, 20)), 1);
+        assert_eq!(level_count((100_000, 100_000)), MAX_SPREAD as usize);
     }
 
-    fn set_sample_uniforms(combine_program: &mut Program, targets: &[[Texture; 5]; 2], brightness: f32, spread: u8) {
-        combine_program.set_uniform("brightness", &(brightness / spread as f32));
-        combine_program.set_uniform("sample0", &targets[0][0]);
-        combine_program.set_uniform("sample1", &targets[0][1]);
-        combine_program.set_uniform("sample2", &targets[0][2]);
-        combine_program.set_uniform("sample3", &targets[0][3]);
-        combine_program.set_uniform("sample4", &targets[0][4]);
+    #[test]
+    fn radius_one_kernel_matches_original_weights() {
+        let sigma = kernel_sigma(1);
+        let weights = [ (-1.0 / (2.0 * sigma * sigma)).exp(), 1.0, (-1.0 / (2.0 * sigma * sigma)).exp() ];
+        let total: f32 = weights.iter().sum();
+        assert!((weights[0] / total - 0.3125).abs() < 0.001);
+        assert!((weights[1] / total - 0.375).abs() < 0.001);
+        assert!(kernel_sigma(MAX_RADIUS) > sigma);
     }
 }
@@ -0,0 +1,228 @@
+use radiant::*;
+use std::sync::Mutex;
+
+/// Color grading LUT a grading effect starts with (no change).
+pub const IDENTITY_LUT: &str = "res/lut/identity.png";
+
+/// Full-screen effect of a `ScreenEffect`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EffectKind {
+    /// Darkens the screen towards the edges.
+    Vignette,
+    /// Curved screen with scanlines.
+    Crt,
+    /// Color channels shifted apart towards the edges.
+    ChromaticAberration,
+    /// Colors mapped through a LUT (256x16 strip of 16 blue slices, red
+    /// along x and green along y within a slice).
+    ColorGrading,
+}
+
+impl EffectKind {
+    /// Uniforms (parameters) of the effect's shader with their default values,
+    /// in the order the shader reads them (texture_uniforms._rd_flags.x, .y, ...).
+    fn uniforms(self) -> &'static [(&'static str, f32)] {
+        match self {
+            EffectKind::Vignette => &[ ("strength", 0.5), ("radius", 0.75), ("softness", 0.45) ],
+            EffectKind::Crt => &[ ("curvature", 0.1), ("scanlines", 0.25) ],
+            EffectKind::ChromaticAberration => &[ ("offset", 2.0) ],
+            EffectKind::ColorGrading => &[ ("strength", 1.0) ],
+        }
+    }
+
+    fn shader(self) -> &'static str {
+        match self {
+            EffectKind::Vignette => include_str!("../res/shader/vignette.wgsl"),
+            EffectKind::Crt => include_str!("../res/shader/crt.wgsl"),
+            EffectKind::ChromaticAberration => include_str!("../res/shader/chromatic.wgsl"),
+            EffectKind::ColorGrading => include_str!("../res/shader/grading.wgsl"),
+        }
+    }
+}
+
+/// Tone mapping operator of the HDR pipeline (`ToneMap`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapping {
+    /// Colors clamped to 1 (after exposure).
+    None,
+    /// c / (1 + c)
+    Reinhard,
+    /// Fitted ACES filmic curve (Narkowicz).
+    Aces,
+}
+
+impl ToneMapping {
+    /// Operator from its API ID (`Api::TONE_MAPPING_*`).
+    pub fn from_id(id: u32) -> Option<ToneMapping> {
+        [ ToneMapping::None, ToneMapping::Reinhard, ToneMapping::Aces ].get(id as usize).copied()
+    }
+}
+
+/// Postprocessor drawing its input through a texture fragment shader (the
+/// preamble provides sheet(), sheetSize(), texture_uniforms and
+/// TextureFragmentInput; scalar uniforms land in texture_uniforms._rd_flags
+/// in the order they are first set).
+pub struct ShaderPass {
+    target      : Texture,
+    program     : Mutex<Program>,
+}
+
+impl Postprocessor for ShaderPass {
+    type T = BlendMode;
+
+    fn target(self: &Self) -> &Texture {
+        &self.target
+    }
+
+    fn process(self: &Self, _: &Renderer, _: &Self::T) { }
+
+    fn draw(self: &Self, renderer: &Renderer, blendmode: &Self::T) {
+        let program = self.program.lock().unwrap();
+        renderer.fill().blendmode(*blendmode).program(&program).texture(&self.target).draw();
+        self.target.clear(Color::TRANSPARENT);
+    }
+}
+
+impl ShaderPass {
+    /// Compile `source` (WGSL) for a display of size `dimensions`.
+    pub fn new(context: &Context, dimensions: (u32, u32), source: &str) -> Result<Self, String> {
+        let program = Program::from_string(context, source).map_err(|e| format!("{:?}", e))?;
+        Ok(ShaderPass {
+            target      : Self::create_target(context, dimensions),
+            program     : Mutex::new(program),
+        })
+    }
+
+    pub fn set_float(&self, name: &str, value: f32) {
+        self.program.lock().unwrap().set_uniform(name, &value);
+    }
+
+    pub fn set_texture(&self, name: &str, texture: &Texture) {
+        self.program.lock().unwrap().set_uniform(name, texture);
+    }
+
+    /// Rebuild the input target for a new display size (display resize).
+    pub fn rebuild(&mut self, context: &Context, dimensions: (u32, u32)) {
+        self.target = Self::create_target(context, dimensions);
+    }
+
+    fn create_target(context: &Context, dimensions: (u32, u32)) -> Texture {
+        Texture::builder(context).format(TextureFormat::F16F16F16F16).dimensions(dimensions).build().unwrap()
+    }
+}
+
+/// Built-in full-screen effect. Vignette and CRT output darkening alpha too, so
+/// they also darken what is below the processed layer.
+pub struct ScreenEffect {
+    kind        : EffectKind,
+    pass        : ShaderPass,
+    /// Current uniform values (same order as `EffectKind::uniforms`).
+    values      : Vec<f32>,
+    /// Color grading LUT.
+    lut         : Option<Texture>,
+}
+
+impl ScreenEffect {
+    pub fn new(context: &Context, dimensions: (u32, u32), kind: EffectKind) -> Self {
+        let lut = if kind == EffectKind::ColorGrading { Some(Texture::from_file(context, IDENTITY_LUT).unwrap()) } else { None };
+        let effect = ScreenEffect {
+            kind        : kind,
+            pass        : ShaderPass::new(context, dimensions, kind.shader()).unwrap(),
+            values      : kind.uniforms().iter().map(|&(_, value)| value).collect(),
+            lut         : lut,
+        };
+        effect.set_uniforms();
+        effect
+    }
+
+    pub fn kind(&self) -> EffectKind {
+        self.kind
+    }
+
+    /// The postprocessor drawing the effect.
+    pub fn pass(&self) -> &ShaderPass {
+        &self.pass
+    }
+
+    /// Set a parameter by uniform name (e.g. "strength"); fails if the effect
+    /// does not have it.
+    pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+        let index = self.kind.uniforms().iter().position(|&(uniform, _)| uniform == name)
+            .ok_or_else(|| format!("{:?} has no {}", self.kind, name))?;
+        self.values[index] = value.max(0.0);
+        self.set_uniforms();
+        Ok(())
+    }
+
+    /// Replace the color grading LUT; fails for other effects.
+    pub fn set_lut(&mut self, lut: Texture) -> Result<(), String> {
+        if self.kind != EffectKind::ColorGrading {
+            return Err(format!("{:?} has no LUT", self.kind));
+        }
+        self.lut = Some(lut);
+        self.set_uniforms();
+        Ok(())
+    }
+
+    /// Rebuild the input target for a new display size (display resize).
+    pub fn rebuild(&mut self, context: &Context, dimensions: (u32, u32)) {
+        self.pass.rebuild(context, dimensions);
+    }
+
+    fn set_uniforms(&self) {
+        for (&(name, _), &value) in self.kind.uniforms().iter().zip(self.values.iter()) {
+            self.pass.set_float(name, value);
+        }
+        if let Some(lut) = &self.lut {
+            self.pass.set_texture("lut", lut);
+        }
+    }
+}
+
+/// Final pass of the HDR pipeline: the game is drawn into its F16 target
+/// (unclamped) and tone mapped to the display.
+pub struct ToneMap {
+    pass        : ShaderPass,
+    mapping     : ToneMapping,
+    exposure    : f32,
+}
+
+impl ToneMap {
+    /// Tone mapping without an operator at exposure 1 (colors clamped, as
+    /// without HDR).
+    pub fn new(context: &Context, dimensions: (u32, u32)) -> Self {
+        let tone_map = ToneMap {
+            pass        : ShaderPass::new(context, dimensions, include_str!("../res/shader/tonemap.wgsl")).unwrap(),
+            mapping     : ToneMapping::None,
+            exposure    : 1.0,
+        };
+        tone_map.set_uniforms();
+        tone_map
+    }
+
+    /// The postprocessor drawing the tone mapped frame.
+    pub fn pass(&self) -> &ShaderPass {
+        &self.pass
+    }
+
+    /// Colors are multiplied by the exposure before tone mapping.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure.max(0.0);
+        self.set_uniforms();
+    }
+
+    pub fn set_mapping(&mut self, mapping: ToneMapping) {
+        self.mapping = mapping;
+        self.set_uniforms();
+    }
+
+    /// Rebuild the HDR target for a new display size (display resize).
+    pub fn rebuild(&mut self, context: &Context, dimensions: (u32, u32)) {
+        self.pass.rebuild(context, dimensions);
+    }
+
+    fn set_uniforms(&self) {
+        self.pass.set_float("exposure", self.exposure);
+        self.pass.set_float("mapping", self.mapping as u32 as f32);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use hecs;
 
 /**
  * Visual component
@@ -16,6 +17,8 @@ pub struct Visual {
     pub effect_color    : Color,
     pub frame_id        : f32,
     pub fps             : u32,
+    /// Draw order within its layers: higher depths are drawn on top.
+    pub depth           : f32,
 }
 
 /**
@@ -124,3 +127,32 @@ pub struct Hitpoints(pub f32);
  */
 #[derive(Clone, Debug, Default)]
 pub struct Script(pub u16);
+
+/**
+ * Parent component
+ *
+ * Entities with this component are attached to another entity: their Spatial is derived
+ * from the parent's Spatial and their LocalTransform each frame (hierarchy system).
+ */
+#[derive(Clone, Debug)]
+pub struct Parent {
+    /// The entity this entity is attached to.
+    pub entity: hecs::Entity,
+    /// Rotate with the parent (the local offset and angle are relative to the parent's angle).
+    pub inherit_angle: bool,
+    /// Despawn together with the parent. Otherwise the child is detached and keeps its last transform.
+    pub despawn_with_parent: bool,
+}
+
+/**
+ * Local transform component
+ *
+ * Position and angle of an attached entity relative to its Parent.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct LocalTransform {
+    /// Offset from the parent's position
+    pub position: Vec2,
+    /// Angle relative to the parent's angle (absolute angle if the angle is not inherited)
+    pub angle: Angle,
+}
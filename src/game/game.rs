@@ -3,46 +3,150 @@ use radiant_utils::maths::Mat4;
 use hecs;
 use rodio::mixer::Mixer;
 use crate::timeframe::Timeframe;
-use crate::game::system::{RenderLayer, RenderBackground};
-use crate::sound::Sound;
-use std::collections::HashMap;
+use crate::game::system::{RenderLayer, RenderFilter, RenderBackground};
+use crate::effects::{ShaderPass, ToneMap};
+use crate::text;
+use crate::audio::Audio;
+use crate::settings::{self, Settings};
+use crate::bindings::Bindings;
+use crate::scripting::{ScriptBudget, ScriptStats, InputMode};
+use std::collections::{HashMap, BTreeMap};
 
-mod component;
+pub mod component;
+mod timer;
 #[path="system/system.rs"]
 mod system;
 
+/// Size of the debug text font (`Infrastructure::font`).
+pub const FONT_SIZE: f32 = 20.0;
+
 pub struct Infrastructure {
     pub input: Input,
+    /// Named input actions queried by the Itsy script (rebindable from the menu).
+    pub bindings: Bindings,
     /// Sprite cache (loaded on first use).
     pub sprite_cache: HashMap<String, Arc<Sprite>>,
-    /// Sound cache (loaded on first play).
-    pub sound_cache: HashMap<String, Sound>,
     /// Background texture cache (loaded on first draw_background).
     pub background_cache: HashMap<String, Arc<Texture>>,
-    pub audio: Mixer,
+    /// Sound playback on volume-controlled buses (sfx, music, ui).
+    pub audio: Audio,
+    /// Player settings (audio volumes), changed from the menu.
+    pub settings: Settings,
+    /// Whether `settings` changed since they were saved (see `save_settings`).
+    pub settings_dirty: bool,
     /// Render layers created by the Itsy script (`create_layer`); the vector
     /// index is the layer ID shared between Itsy and Rust.
     pub layers: Vec<Arc<Layer>>,
     /// The `create_layer` scale of each layer in `layers` (parallel vector;
     /// needed to re-apply the layer view matrix on a display resize).
     pub layer_scales: Vec<f32>,
+    /// The program each layer in `layers` is drawn through (parallel vector,
+    /// `set_layer_program`; 0 = none).
+    pub layer_programs: Vec<u32>,
+    /// Layers for entities drawn through a program (`set_entity_program`), by
+    /// (layer ID, program ID); drawn after their layer in its render passes.
+    pub program_layers: BTreeMap<(u32, u32), Arc<Layer>>,
     /// Render passes created by the Itsy script (`add_render_layer`), in draw order.
     pub render_layers: Vec<RenderLayer>,
+    /// Post-processing filters created by the Itsy script (`create_filter`);
+    /// filter ID = index + 1 (0 = no filter).
+    pub filters: Vec<RenderFilter>,
+    /// Shader programs compiled by the Itsy script (`create_program`);
+    /// program ID = index + 1 (0 = no program), None if compiling failed.
+    pub programs: Vec<Option<ShaderPass>>,
+    /// HDR target the frame is drawn into, tone mapped to the display
+    /// (`set_exposure`, `set_tone_mapping`).
+    pub tone_map: ToneMap,
     /// Background images to show this frame (`draw_background`), in draw order.
     /// Rebuilt by the scripting system each frame (cleared before execution).
     pub background_draws: Vec<RenderBackground>,
+    /// Font of the Rust side debug text.
     pub font: Arc<Font>,
-    pub menu_font: Arc<Font>,
+    /// Fonts created by the Itsy script (`create_font`); font ID = index + 1
+    /// (0 = no font), None if loading failed.
+    pub fonts: Vec<Option<Arc<Font>>>,
     pub display: Arc<Display>,
     /// Layer ID used for Rust-side debug text (set by Itsy via `set_debug_layer`), `u32::MAX` = not set yet. // FIXME: use Option
     pub debug_layer: u32,
+    /// Whether the game systems write debug text this frame (not into clean
+    /// screenshots or offline frames, see `Game::set_debug_text`).
+    pub debug_text: bool,
 }
 
 impl Infrastructure {
-    /// The layer designated for Rust-side debug text, if the script set one.
+    /// The layer designated for Rust-side debug text, if the script set one
+    /// and debug text is shown this frame.
     pub fn debug_layer(&self) -> Option<Arc<Layer>> {
+        if !self.debug_text {
+            return None;
+        }
         self.layers.get(self.debug_layer as usize).cloned()
     }
+
+    /// Save the settings if they changed since the last save. Done when the
+    /// script asks (`save_settings`, e.g. as the audio menu closes) and before
+    /// the game ends or restarts, rather than on every change (e.g. each volume
+    /// step).
+    pub fn save_settings(&mut self) {
+        if self.settings_dirty {
+            if let Err(e) = self.settings.save() {
+                eprintln!("settings: cannot save '{}': {}", settings::USER_PATH, e);
+            }
+            self.settings_dirty = false;
+        }
+    }
+
+    /// The filter with ID `filter_id`, if it exists.
+    pub fn filter(&self, filter_id: u32) -> Option<&RenderFilter> {
+        self.filters.get((filter_id as usize).wrapping_sub(1))
+    }
+
+    /// The program with ID `program_id`, if it exists and compiled.
+    pub fn program(&self, program_id: u32) -> Option<&ShaderPass> {
+        self.programs.get((program_id as usize).wrapping_sub(1))?.as_ref()
+    }
+
+    /// The font with ID `font_id`, if it exists and loaded.
+    pub fn font(&self, font_id: u32) -> Option<&Arc<Font>> {
+        self.fonts.get((font_id as usize).wrapping_sub(1))?.as_ref()
+    }
+
+    /// Drop the render resources created by the Itsy script (layers, passes,
+    /// filters, programs, fonts), e.g. before it restarts and creates them again.
+    pub fn clear_script_resources(&mut self) {
+        self.layers.clear();
+        self.layer_scales.clear();
+        self.layer_programs.clear();
+        self.program_layers.clear();
+        self.render_layers.clear();
+        self.filters.clear();
+        self.programs.clear();
+        self.fonts.clear();
+        self.background_draws.clear();
+        self.debug_layer = u32::MAX;
+    }
+
+    /// The layer to draw sprites of `layer` (a script layer or a program
+    /// layer) into when drawn through `program_id` (0 = the script layer
+    /// itself). Program layers are created on first use.
+    pub fn program_layer(&mut self, layer: &Arc<Layer>, program_id: u32) -> Option<Arc<Layer>> {
+        let layer_id = match self.layers.iter().position(|other| Arc::ptr_eq(other, layer)) {
+            Some(layer_id) => layer_id as u32,
+            None => self.program_layers.iter().find(|(_, other)| Arc::ptr_eq(other, layer))?.0.0,
+        };
+        if program_id == 0 {
+            return self.layers.get(layer_id as usize).cloned();
+        }
+        let base = self.layers.get(layer_id as usize)?;
+        let scale = self.layer_scales[layer_id as usize];
+        let (w, h) = self.display.dimensions();
+        let program_layer = self.program_layers.entry((layer_id, program_id)).or_insert_with(|| {
+            let program_layer = Layer::new((scale * w as f32, scale * h as f32)).arc();
+            program_layer.set_blendmode(base.blendmode());
+            program_layer
+        });
+        Some(program_layer.clone())
+    }
 }
 
 pub struct State {
@@ -55,7 +159,12 @@ pub struct State {
     /// Set by the Itsy script (`set_resolution`); the main loop applies it
     /// after swap_frame (Option so `take_resolution_request` can consume it).
     pub resolution_requested: Option<(u32, u32)>,
+    /// Set by the Itsy script (`take_screenshot`): capture the next frame,
+    /// with (true) or without the debug overlay.
+    pub screenshot_requested: Option<bool>,
     pub fullscreen: bool,
+    /// Timers started by the Itsy script (`start_timer`), on game time.
+    pub timers: timer::Timers,
 }
 
 pub struct Game {
@@ -64,6 +173,8 @@ pub struct Game {
     scripting       : system::Scripting,
     inf             : Infrastructure,
     state           : State,
+    /// Real (or simulated) time of the last `process` call, None before the first.
+    last_frame      : Option<Instant>,
 }
 
 impl Game {
@@ -72,25 +183,32 @@ impl Game {
 
         let world = hecs::World::new();
         let context = display.context().clone();
-        let font = Font::builder(&context).family("Arial").size(20.0).build().unwrap().arc();
-        let menu_font = Font::builder(&context).family("Arial").size(80.0).bold().build().unwrap().arc();
+        let font = text::load_font(&context, text::DEFAULT_FONT, FONT_SIZE).unwrap().arc();
 
-        let dimensions = display.dimensions();
+        let settings = Settings::load();
 
         let infrastructure = Infrastructure {
             sprite_cache        : HashMap::new(),
-            sound_cache         : HashMap::new(),
             background_cache    : HashMap::new(),
-            audio               : audio.clone(),
+            audio               : Audio::new(audio, &settings.audio),
+            settings            : settings,
+            settings_dirty      : false,
             input               : input.clone(),
+            bindings            : Bindings::load(),
             layers              : Vec::new(),
             layer_scales        : Vec::new(),
+            layer_programs      : Vec::new(),
+            program_layers      : BTreeMap::new(),
             render_layers       : Vec::new(),
+            filters             : Vec::new(),
+            programs            : Vec::new(),
+            tone_map            : ToneMap::new(&context, display.dimensions()),
             background_draws    : Vec::new(),
             font                : font,
-            menu_font           : menu_font,
+            fonts               : Vec::new(),
             display             : display,
             debug_layer         : u32::MAX,
+            debug_text          : true,
         };
 
         let state = State {
@@ -98,13 +216,19 @@ impl Game {
             exit_requested      : false,
             restart_requested   : false,
             resolution_requested: None,
+            screenshot_requested: None,
             fullscreen          : fullscreen,
+            timers              : timer::Timers::new(),
         };
 
+        let mut scripting = system::Scripting::new();
+        scripting.set_budget(infrastructure.settings.script.budget());
+
         Game {
             world           : world,
-            render_system   : system::Render::new(&context, dimensions),
-            scripting       : system::Scripting::new(),
+            render_system   : system::Render::new(),
+            scripting       : scripting,
+            last_frame      : None,
             inf             : infrastructure,
             state           : state,
         }
@@ -135,11 +259,76 @@ impl Game {
         self.state.fullscreen
     }
 
+    /// Timing and instruction count of the last frame's script run (debug overlay).
+    pub fn script_stats(&self) -> ScriptStats {
+        self.scripting.stats()
+    }
+
+    /// Run the game on a simulated clock, advanced only by `advance_time`
+    /// (offline rendering at a fixed delta). Call right after `new`.
+    pub fn simulate_time(&mut self) {
+        self.state.timeframe = Timeframe::simulated();
+        self.inf.audio.simulate_time();
+        // script results must not depend on how fast the frames are computed
+        self.scripting.set_budget(ScriptBudget { max_time: None, ..self.inf.settings.script.budget() });
+    }
+
+    /// Advance the simulated clock by `delta` real time (see `simulate_time`).
+    pub fn advance_time(&mut self, delta: Duration) {
+        self.state.timeframe.advance(delta);
+        self.inf.audio.advance_time(delta);
+    }
+
+    /// Replace the script input mode (live, recorded or replayed input).
+    /// A replay runs on simulated time (see `replay_delta`). Call right after `new`.
+    pub fn set_input_mode(&mut self, mode: InputMode) {
+        if matches!(mode, InputMode::Play(_)) && !self.state.timeframe.is_simulated() {
+            self.simulate_time();
+        }
+        self.scripting.set_input_mode(mode);
+    }
+
+    /// True if the script input is replayed (the game runs on simulated time).
+    pub fn is_replaying(&self) -> bool {
+        self.scripting.is_replaying()
+    }
+
+    /// Real time to advance the simulated clock by before the next replayed
+    /// frame: its recorded delta (None unless replaying, or once the replay
+    /// is over).
+    pub fn replay_delta(&self) -> Option<Duration> {
+        self.scripting.replay_delta()
+    }
+
+    /// Save the settings changed from the menu, if any (before the game ends
+    /// or restarts).
+    pub fn save_settings(&mut self) {
+        self.inf.save_settings();
+    }
+
+    /// Take the script input mode (e.g. to save a recording, or to keep it
+    /// across a level restart), leaving live input.
+    pub fn take_input_mode(&mut self) -> InputMode {
+        self.scripting.take_input_mode()
+    }
+
     /// Consume a pending resolution change requested by the Itsy script.
     pub fn take_resolution_request(&mut self) -> Option<(u32, u32)> {
         std::mem::take(&mut self.state.resolution_requested)
     }
 
+    /// Show or hide the debug text the game systems write into the script's
+    /// debug layer, for the next frames (hidden for clean captures).
+    pub fn set_debug_text(&mut self, enabled: bool) {
+        self.inf.debug_text = enabled;
+    }
+
+    /// Consume a pending screenshot request of the Itsy script: Some(true) to
+    /// capture the next frame with the debug overlay, Some(false) without.
+    pub fn take_screenshot_request(&mut self) -> Option<bool> {
+        self.state.screenshot_requested.take()
+    }
+
     /// Apply a live display resize (called by the main loop after swap_frame,
     /// when no frame is prepared). Re-sets the script layers' view matrices to
     /// the new size (keeping their scales) and rebuilds the postprocessors.
@@ -165,20 +354,41 @@ impl Game {
             let matrix = Mat4::viewport(*scale * w as f32, *scale * h as f32);
             layer.set_view_matrix(matrix.0);
         }
-        self.render_system.resize(&self.inf.display.context(), (w, h));
+        for (&(layer_id, _), layer) in self.inf.program_layers.iter() {
+            let scale = self.inf.layer_scales[layer_id as usize];
+            layer.set_view_matrix(Mat4::viewport(scale * w as f32, scale * h as f32).0);
+        }
+        for filter in self.inf.filters.iter_mut() {
+            filter.resize(&self.inf.display.context(), (w, h));
+        }
+        for program in self.inf.programs.iter_mut().flatten() {
+            program.rebuild(&self.inf.display.context(), (w, h));
+        }
+        self.inf.tone_map.rebuild(&self.inf.display.context(), (w, h));
     }
 
     /// Process a game frame.
     pub fn process(&mut self, renderer: &Renderer, age: f32, delta: f32) {
 
-        // Run scripting subsystem and apply script commands
-        self.scripting.prepare_frame(&mut self.world, &mut self.inf, age);
+        // Run scripting subsystem and apply script commands. Sounds the script
+        // plays follow the game time of this frame.
+        self.inf.audio.set_game_time(self.state.timeframe.elapsed(), self.state.timeframe.rate() as f32, self.state.timeframe.target_rate() as f32);
+        let now = self.state.timeframe.now();
+        let real_delta = self.last_frame.map_or(Duration::ZERO, |last_frame| now.saturating_duration_since(last_frame));
+        self.last_frame = Some(now);
+        self.scripting.prepare_frame(&mut self.world, &mut self.inf, age, real_delta);
         let mut cmd = hecs::CommandBuffer::new();
         self.scripting.run(&mut self.world, &mut self.inf, &mut self.state, &mut cmd);
         cmd.run_on(&mut self.world);
+        let world = &self.world;
+        self.inf.audio.update(self.inf.display.dimensions(), |id| {
+            let entity = hecs::Entity::from_bits(id)?;
+            world.get::<&component::Spatial>(entity).ok().map(|spatial| spatial.position)
+        });
 
         // Shared systems
         system::run_inertia(&mut self.world, delta, &self.inf);
+        system::run_hierarchy(&mut self.world);
         system::run_collider(&mut self.world);
         self.render_system.run(&mut self.world, age, delta, &self.inf, renderer);
         system::run_cleanup(&mut self.world, age);
@@ -16,6 +16,26 @@ pub fn run(world: &mut hecs::World, age: f32) {
         }
     }
 
+    // Children despawned with their parent go with it, like dying entities (the
+    // scripting snapshot then reports them dying, for on_die). Repeated for
+    // nested children.
+    loop {
+        let children: Vec<hecs::Entity> = world
+            .query::<&component::Parent>()
+            .iter()
+            .filter(|&(entity, parent)| {
+                parent.despawn_with_parent
+                    && !to_despawn.contains(&entity)
+                    && (to_despawn.contains(&parent.entity) || !world.contains(parent.entity))
+            })
+            .map(|(entity, _)| entity)
+            .collect();
+        if children.is_empty() {
+            break;
+        }
+        to_despawn.extend(children);
+    }
+
     to_despawn.sort_unstable();
     to_despawn.dedup();
 
@@ -23,3 +43,30 @@ pub fn run(world: &mut hecs::World, age: f32) {
         let _ = world.despawn(entity);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_child(world: &mut hecs::World, parent: hecs::Entity, despawn_with_parent: bool) -> hecs::Entity {
+        world.spawn((component::Parent { entity: parent, inherit_angle: true, despawn_with_parent },))
+    }
+
+    #[test]
+    fn children_die_with_their_parent() {
+        let mut world = hecs::World::new();
+        let parent = world.spawn((component::Hitpoints(0.0),));
+        let child = spawn_child(&mut world, parent, true);
+        let grandchild = spawn_child(&mut world, child, true);
+        let detached = spawn_child(&mut world, parent, false);
+        let other = world.spawn((component::Hitpoints(1.0),));
+        let other_child = spawn_child(&mut world, other, true);
+        run(&mut world, 0.0);
+        assert!(!world.contains(parent) && !world.contains(child) && !world.contains(grandchild));
+        assert!(world.contains(detached) && world.contains(other) && world.contains(other_child));
+        // parent despawned elsewhere (e.g. by the script)
+        world.despawn(other).unwrap();
+        run(&mut world, 0.0);
+        assert!(!world.contains(other_child));
+    }
+}
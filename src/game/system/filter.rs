@@ -0,0 +1,273 @@
+use crate::prelude::*;
+use crate::bloom;
+use crate::effects::{ScreenEffect, EffectKind};
+
+/// Kind of a post-processing filter (`create_filter`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    /// radiant's bloom: the layer blurred and drawn over itself.
+    Bloom,
+    /// Glare (bloom.rs): blurred downsampled levels combined with screen blending.
+    Glare,
+    /// Full-screen effect (effects.rs).
+    Effect(EffectKind),
+}
+
+impl FilterKind {
+    /// Kind from its API ID (`Api::FILTER_*`, FILTER_NONE excluded).
+    pub fn from_id(id: u32) -> Option<FilterKind> {
+        [
+            FilterKind::Bloom, FilterKind::Glare, FilterKind::Effect(EffectKind::Vignette), FilterKind::Effect(EffectKind::Crt),
+            FilterKind::Effect(EffectKind::ChromaticAberration), FilterKind::Effect(EffectKind::ColorGrading),
+        ].get((id as usize).wrapping_sub(1)).copied()
+    }
+}
+
+/// Adjustable filter parameter (`set_filter_param`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterParam {
+    Iterations,
+    Spread,
+    Brightness,
+    Fade,
+    Strength,
+    Radius,
+    Softness,
+    Curvature,
+    Scanlines,
+    Offset,
+    Threshold,
+    Knee,
+    Kernel,
+    Clamp,
+}
+
+impl FilterParam {
+    const ALL: [FilterParam; 14] = [
+        FilterParam::Iterations, FilterParam::Spread, FilterParam::Brightness, FilterParam::Fade, FilterParam::Strength,
+        FilterParam::Radius, FilterParam::Softness, FilterParam::Curvature, FilterParam::Scanlines, FilterParam::Offset,
+        FilterParam::Threshold, FilterParam::Knee, FilterParam::Kernel, FilterParam::Clamp,
+    ];
+
+    /// Parameter from its API ID (`Api::FILTER_PARAM_*`, same order as `ALL`).
+    pub fn from_id(id: u32) -> Option<FilterParam> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    /// Parameter name; for screen effects the name of the shader uniform.
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterParam::Iterations => "iterations",
+            FilterParam::Spread => "spread",
+            FilterParam::Brightness => "brightness",
+            FilterParam::Fade => "fade",
+            FilterParam::Strength => "strength",
+            FilterParam::Radius => "radius",
+            FilterParam::Softness => "softness",
+            FilterParam::Curvature => "curvature",
+            FilterParam::Scanlines => "scanlines",
+            FilterParam::Offset => "offset",
+            FilterParam::Threshold => "threshold",
+            FilterParam::Knee => "knee",
+            FilterParam::Kernel => "kernel",
+            FilterParam::Clamp => "clamp",
+        }
+    }
+}
+
+/// Parameters of a bloom or glare filter (screen effects keep theirs in the
+/// `ScreenEffect`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterParams {
+    /// Blur passes.
+    pub iterations  : u32,
+    /// Number of downsampled levels blurred (glare only, 1..=MAX_SPREAD).
+    pub spread      : u8,
+    /// Bloom: alpha of the blurred layer drawn over it; glare: brightness of
+    /// the combined levels.
+    pub brightness  : f32,
+    /// Alpha of the black fill drawn over the filter input before the layer:
+    /// the input is not cleared, so lower values leave longer trails. 0 = no fill.
+    pub fade        : f32,
+    /// Glare only (see `bloom::BloomParams`): brightness below which pixels
+    /// do not glow, soft transition width, blur kernel radius and output limit.
+    pub threshold   : f32,
+    pub knee        : f32,
+    pub kernel      : u32,
+    pub clamp       : f32,
+}
+
+impl FilterParams {
+    /// Parameters a filter of `kind` is created with.
+    pub fn default_for(kind: FilterKind) -> Self {
+        let glare = bloom::BloomParams::default();
+        let params = FilterParams {
+            iterations  : glare.iterations,
+            spread      : glare.spread,
+            brightness  : glare.brightness,
+            fade        : 0.05,
+            threshold   : glare.threshold,
+            knee        : glare.knee,
+            kernel      : glare.radius,
+            clamp       : glare.clamp,
+        };
+        match kind {
+            FilterKind::Bloom => FilterParams { iterations: 2, spread: 1, brightness: 0.15, fade: 0.3, ..params },
+            FilterKind::Glare => params,
+            FilterKind::Effect(_) => FilterParams { iterations: 0, spread: 1, brightness: 0.0, fade: 0.0, ..params },
+        }
+    }
+
+    /// Set a parameter; fails for parameters the filter kind does not have.
+    pub fn set(&mut self, kind: FilterKind, param: FilterParam, value: f32) -> Result<(), String> {
+        match param {
+            FilterParam::Iterations => self.iterations = value.max(0.0) as u32,
+            FilterParam::Spread if kind == FilterKind::Glare => self.spread = value.clamp(1.0, bloom::MAX_SPREAD as f32) as u8,
+            FilterParam::Spread => return Err(format!("{:?} has no spread", kind)),
+            FilterParam::Brightness => self.brightness = value.max(0.0),
+            FilterParam::Fade => self.fade = value.clamp(0.0, 1.0),
+            FilterParam::Threshold if kind == FilterKind::Glare => self.threshold = value.max(0.0),
+            FilterParam::Knee if kind == FilterKind::Glare => self.knee = value.max(0.0),
+            FilterParam::Kernel if kind == FilterKind::Glare => self.kernel = value.clamp(1.0, bloom::MAX_RADIUS as f32) as u32,
+            FilterParam::Clamp if kind == FilterKind::Glare => self.clamp = value.max(0.0),
+            _ => return Err(format!("{:?} has no {}", kind, param.name())),
+        }
+        Ok(())
+    }
+
+    /// The glare parameters.
+    fn glare(&self) -> bloom::BloomParams {
+        bloom::BloomParams {
+            iterations  : self.iterations,
+            spread      : self.spread,
+            brightness  : self.brightness,
+            threshold   : self.threshold,
+            knee        : self.knee,
+            radius      : self.kernel,
+            clamp       : self.clamp,
+        }
+    }
+}
+
+enum Processor {
+    Bloom(postprocessors::Bloom),
+    Glare(bloom::Bloom),
+    Effect(ScreenEffect),
+}
+
+/// A named post-processing filter instance created by the Itsy script. Render
+/// passes (`add_render_layer`) chain filters by their IDs.
+pub struct RenderFilter {
+    kind        : FilterKind,
+    params      : FilterParams,
+    processor   : Processor,
+}
+
+impl RenderFilter {
+    /// A filter of `kind` with its default parameters for a display of size `dimensions`.
+    pub fn new(context: &Context, dimensions: (u32, u32), kind: FilterKind) -> Self {
+        let params = FilterParams::default_for(kind);
+        let processor = match kind {
+            FilterKind::Bloom => {
+                let mut bloom = postprocessors::Bloom::new(context, dimensions, params.iterations);
+                bloom.clear = false;
+                bloom.draw_color = Color::alpha_pm(params.brightness);
+                Processor::Bloom(bloom)
+            }
+            FilterKind::Glare => Processor::Glare(bloom::Bloom::new(context, dimensions, params.glare())),
+            FilterKind::Effect(effect) => Processor::Effect(ScreenEffect::new(context, dimensions, effect)),
+        };
+        RenderFilter { kind, params, processor }
+    }
+
+    /// Change a parameter; applies from the next frame.
+    pub fn set_param(&mut self, context: &Context, dimensions: (u32, u32), param: FilterParam, value: f32) -> Result<(), String> {
+        if let Processor::Effect(effect) = &mut self.processor {
+            return effect.set(param.name(), value);
+        }
+        let previous = self.params;
+        self.params.set(self.kind, param, value)?;
+        match &mut self.processor {
+            Processor::Bloom(bloom) => {
+                bloom.draw_color = Color::alpha_pm(self.params.brightness);
+                if self.params.iterations != previous.iterations {
+                    bloom.rebuild(context, dimensions, self.params.iterations);
+                }
+            }
+            Processor::Glare(glare) => glare.set_params(self.params.glare()),
+            Processor::Effect(_) => { }
+        }
+        Ok(())
+    }
+
+    /// Replace the LUT of a color grading filter.
+    pub fn set_lut(&mut self, lut: Texture) -> Result<(), String> {
+        match &mut self.processor {
+            Processor::Effect(effect) => effect.set_lut(lut),
+            _ => Err(format!("{:?} has no LUT", self.kind)),
+        }
+    }
+
+    /// Rebuild the render targets for a new display size (display resize).
+    pub fn resize(&mut self, context: &Context, dimensions: (u32, u32)) {
+        match &mut self.processor {
+            Processor::Bloom(bloom) => bloom.rebuild(context, dimensions, self.params.iterations),
+            Processor::Glare(glare) => glare.rebuild(context, dimensions),
+            Processor::Effect(effect) => effect.rebuild(context, dimensions),
+        }
+    }
+
+    /// Draw `draw` through the filter into the current target.
+    pub fn apply(&self, renderer: &Renderer, draw: &dyn Fn()) {
+        let fill = || {
+            if self.params.fade > 0.0 {
+                renderer.fill().color(Color::alpha_mask(self.params.fade)).draw();
+            }
+            draw();
+        };
+        match &self.processor {
+            Processor::Bloom(bloom) => renderer.postprocess(bloom, &(), fill),
+            Processor::Glare(glare) => renderer.postprocess(glare, &blendmodes::SCREEN, fill),
+            Processor::Effect(effect) => renderer.postprocess(effect.pass(), &blendmodes::ALPHA, fill),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_are_clamped_per_kind() {
+        let mut params = FilterParams::default_for(FilterKind::Glare);
+        params.set(FilterKind::Glare, FilterParam::Spread, 9.0).unwrap();
+        assert_eq!(params.spread, bloom::MAX_SPREAD);
+        params.set(FilterKind::Glare, FilterParam::Spread, 0.0).unwrap();
+        assert_eq!(params.spread, 1);
+        params.set(FilterKind::Glare, FilterParam::Fade, 2.0).unwrap();
+        assert_eq!(params.fade, 1.0);
+        params.set(FilterKind::Glare, FilterParam::Iterations, 3.7).unwrap();
+        assert_eq!(params.iterations, 3);
+
+        let mut params = FilterParams::default_for(FilterKind::Bloom);
+        assert!(params.set(FilterKind::Bloom, FilterParam::Spread, 3.0).is_err());
+        params.set(FilterKind::Bloom, FilterParam::Brightness, 0.4).unwrap();
+        assert_eq!(params.brightness, 0.4);
+        assert!(params.set(FilterKind::Bloom, FilterParam::Curvature, 0.1).is_err());
+        assert!(params.set(FilterKind::Bloom, FilterParam::Threshold, 0.5).is_err());
+
+        let mut params = FilterParams::default_for(FilterKind::Glare);
+        assert_eq!(params.glare(), bloom::BloomParams::default());
+        params.set(FilterKind::Glare, FilterParam::Kernel, 20.0).unwrap();
+        assert_eq!(params.kernel, bloom::MAX_RADIUS);
+        params.set(FilterKind::Glare, FilterParam::Threshold, -1.0).unwrap();
+        assert_eq!(params.threshold, 0.0);
+        assert_eq!(FilterParam::from_id(3), Some(FilterParam::Fade));
+        assert_eq!(FilterParam::from_id(9), Some(FilterParam::Offset));
+        assert_eq!(FilterParam::from_id(13), Some(FilterParam::Clamp));
+        assert_eq!(FilterParam::from_id(14), None);
+        assert_eq!(FilterKind::from_id(0), None);
+        assert_eq!(FilterKind::from_id(4), Some(FilterKind::Effect(EffectKind::Crt)));
+        assert_eq!(FilterKind::from_id(7), None);
+    }
+}
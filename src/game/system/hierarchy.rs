@@ -0,0 +1,163 @@
+use crate::prelude::*;
+use hecs;
+use crate::game::component;
+
+pub fn run(world: &mut hecs::World) {
+
+    // Collect attached entities, parents closer to the root first so that nested
+    // children see their parent's transform of this frame.
+    let mut children: Vec<(u32, hecs::Entity, component::Parent, component::LocalTransform)> = world
+        .query::<(&component::Parent, &component::LocalTransform)>()
+        .iter()
+        .map(|(e, (parent, local))| (depth(world, parent.entity), e, parent.clone(), local.clone()))
+        .collect();
+
+    children.sort_by_key(|&(depth, ..)| depth);
+
+    let mut orphans = Vec::new();
+
+    for (_, entity, parent, local) in children {
+        let parent_spatial = match world.get::<&component::Spatial>(parent.entity) {
+            Ok(spatial) => (*spatial).clone(),
+            Err(_) => {
+                if !parent.despawn_with_parent {
+                    orphans.push(entity);
+                }
+                continue;
+            }
+        };
+        if let Ok(mut spatial) = world.get::<&mut component::Spatial>(entity) {
+            let (position, angle) = world_transform(&parent_spatial, &local, parent.inherit_angle);
+            spatial.position = position;
+            spatial.angle = angle;
+        }
+    }
+
+    // Children of dead parents are detached (they keep their last world transform
+    // and resume their own motion, if any). Those despawned with their parent are
+    // left to the cleanup system, which despawns them like dying entities.
+    for entity in orphans {
+        let _ = world.remove::<(component::Parent, component::LocalTransform)>(entity);
+    }
+}
+
+/// True if `ancestor` is `entity` or one of its (transitive) parents. Attaching
+/// `ancestor` to `entity` would form a cycle then.
+pub fn is_ancestor(world: &hecs::World, ancestor: hecs::Entity, mut entity: hecs::Entity) -> bool {
+    loop {
+        if entity == ancestor {
+            return true;
+        }
+        match world.get::<&component::Parent>(entity) {
+            Ok(parent) => entity = parent.entity,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Number of ancestors above an attached entity (bounded to guard against cycles).
+fn depth(world: &hecs::World, mut parent: hecs::Entity) -> u32 {
+    let mut depth = 0;
+    while depth < 16 {
+        match world.get::<&component::Parent>(parent) {
+            Ok(next) => parent = next.entity,
+            Err(_) => break,
+        }
+        depth += 1;
+    }
+    depth
+}
+
+/// Computes a child's world position and angle from its parent's Spatial and its local transform.
+fn world_transform(parent: &component::Spatial, local: &component::LocalTransform, inherit_angle: bool) -> (Vec2, Angle) {
+    if inherit_angle {
+        let (sin, cos) = parent.angle.to_radians().sin_cos();
+        let offset = Vec2(local.position.0 * cos - local.position.1 * sin, local.position.0 * sin + local.position.1 * cos);
+        (parent.position + offset, (parent.angle + local.angle).normalize())
+    } else {
+        (parent.position + local.position, local.angle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn spawn_parent(world: &mut hecs::World, position: Vec2, angle: f32) -> hecs::Entity {
+        world.spawn((component::Spatial { position, angle: Angle(angle), lean: 0.0 },))
+    }
+
+    fn spawn_child(world: &mut hecs::World, parent: hecs::Entity, offset: Vec2, inherit_angle: bool, despawn_with_parent: bool) -> hecs::Entity {
+        world.spawn((
+            component::Spatial::default(),
+            component::Parent { entity: parent, inherit_angle, despawn_with_parent },
+            component::LocalTransform { position: offset, angle: Angle(0.0) },
+        ))
+    }
+
+    fn position(world: &hecs::World, entity: hecs::Entity) -> Vec2 {
+        world.get::<&component::Spatial>(entity).unwrap().position
+    }
+
+    #[test]
+    fn child_follows_parent_rotation() {
+        let mut world = hecs::World::new();
+        let parent = spawn_parent(&mut world, Vec2(10.0, 0.0), FRAC_PI_2);
+        let child = spawn_child(&mut world, parent, Vec2(5.0, 0.0), true, true);
+        run(&mut world);
+        let pos = position(&world, child);
+        assert!((pos.0 - 10.0).abs() < 1e-4 && (pos.1 - 5.0).abs() < 1e-4, "got {:?}", pos);
+        let angle = world.get::<&component::Spatial>(child).unwrap().angle;
+        assert!((angle.to_radians() - FRAC_PI_2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn child_ignores_parent_rotation_without_inheritance() {
+        let mut world = hecs::World::new();
+        let parent = spawn_parent(&mut world, Vec2(10.0, 0.0), FRAC_PI_2);
+        let child = spawn_child(&mut world, parent, Vec2(5.0, 0.0), false, true);
+        run(&mut world);
+        let pos = position(&world, child);
+        assert!((pos.0 - 15.0).abs() < 1e-4 && pos.1.abs() < 1e-4, "got {:?}", pos);
+    }
+
+    #[test]
+    fn nested_children_use_current_parent_transform() {
+        let mut world = hecs::World::new();
+        let root = spawn_parent(&mut world, Vec2(0.0, 0.0), 0.0);
+        let middle = spawn_child(&mut world, root, Vec2(10.0, 0.0), true, true);
+        let leaf = spawn_child(&mut world, middle, Vec2(1.0, 0.0), true, true);
+        run(&mut world);
+        assert!((position(&world, leaf).0 - 11.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ancestors_are_found_along_the_parent_chain() {
+        let mut world = hecs::World::new();
+        let root = spawn_parent(&mut world, Vec2(0.0, 0.0), 0.0);
+        let middle = spawn_child(&mut world, root, Vec2(10.0, 0.0), true, true);
+        let leaf = spawn_child(&mut world, middle, Vec2(1.0, 0.0), true, true);
+        let other = spawn_parent(&mut world, Vec2(0.0, 0.0), 0.0);
+        assert!(is_ancestor(&world, root, leaf));
+        assert!(is_ancestor(&world, leaf, leaf));
+        // attaching root to leaf would close a cycle, attaching it to other not
+        assert!(!is_ancestor(&world, leaf, root));
+        assert!(!is_ancestor(&world, root, other));
+    }
+
+    #[test]
+    fn orphans_are_detached_or_left_to_cleanup() {
+        let mut world = hecs::World::new();
+        let parent = spawn_parent(&mut world, Vec2(10.0, 0.0), 0.0);
+        let despawned = spawn_child(&mut world, parent, Vec2(5.0, 0.0), true, true);
+        let detached = spawn_child(&mut world, parent, Vec2(5.0, 0.0), true, false);
+        run(&mut world);
+        world.despawn(parent).unwrap();
+        run(&mut world);
+        assert!(world.get::<&component::Parent>(despawned).is_ok());
+        assert!(world.contains(detached));
+        assert!(world.get::<&component::Parent>(detached).is_err());
+        assert!((position(&world, detached).0 - 15.0).abs() < 1e-4);
+    }
+}
@@ -2,24 +2,22 @@ use crate::prelude::*;
 use hecs;
 use crate::game::component;
 use crate::game::Infrastructure;
-use crate::bloom;
+use crate::effects::ShaderPass;
+use super::RenderFilter;
 use std::cmp;
 
-/// A filtered render pass on a layer (created by the Itsy script via
+/// A render pass on a layer (created by the Itsy script via
 /// `add_render_layer`), mirroring the old layer.yaml "render" section.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct RenderLayer {
     pub layer_id  : u32,
-    pub filter    : Option<RenderFilter>,
+    /// Filter chain (IDs from `create_filter`, see `Infrastructure::filter`):
+    /// the layer is drawn through the first filter, its output through the
+    /// second, and so on.
+    pub filters   : Vec<u32>,
     pub component : u32,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum RenderFilter {
-    Bloom,
-    Glare,
-}
-
 /// A background image draw requested by the Itsy script (`draw_background`),
 /// resolved to a loaded texture. Drawn below all render layers; the image is
 /// scaled to cover the display and tiled (wrapped) around the given scroll
@@ -36,53 +34,40 @@ pub struct Render {
     fps_interval: Periodic,
     num_frames: u32,
     last_num_frames: u32,
-    bloom: postprocessors::Bloom,
-    glare: bloom::Bloom,
 }
 
 impl Render {
-    pub fn new(radiant_ctx: &Context, dimensions: (u32, u32)) -> Self {
-
-        let mut bloom = postprocessors::Bloom::new(radiant_ctx, dimensions, 2);
-        bloom.clear = false;
-        bloom.draw_color = Color::alpha_pm(0.15);
-
+    pub fn new() -> Self {
         Render {
             fps_interval: Periodic::new(0.0, 1.0),
             num_frames: 0,
             last_num_frames: 0,
-            bloom           : bloom,
-            glare           : bloom::Bloom::new(radiant_ctx, dimensions, 2, 5, 5.0),
         }
     }
 
-    /// Rebuild the postprocessor render targets for a new display size
-    /// (display resize).
-    pub fn resize(&mut self, radiant_ctx: &Context, dimensions: (u32, u32)) {
-        self.bloom.rebuild(radiant_ctx, dimensions, 2);
-        self.glare.rebuild(radiant_ctx, dimensions);
-    }
-
     pub fn run(&mut self, world: &mut hecs::World, age: f32, delta: f32, inf: &Infrastructure, renderer: &Renderer) {
-        let mut num_sprites = 0;
-
-        for (_entity, (spatial, visual, fading)) in world.query_mut::<(
-            &component::Spatial,
-            &mut component::Visual,
-            Option<&component::Fading>,
-        )>() {
-            if let Some(fading) = fading {
-                if age >= fading.start {
-                    let duration = fading.end - fading.start;
-                    let progress = age - fading.start;
-                    let alpha = 1.0 - (progress / duration);
-                    if alpha >= 0.0 {
-                        visual.color.set_a(alpha);
-                        visual.effect_color.set_a(alpha);
-                    }
+        for (_entity, (visual, fading)) in world.query_mut::<(&mut component::Visual, &component::Fading)>() {
+            if age >= fading.start {
+                let duration = fading.end - fading.start;
+                let progress = age - fading.start;
+                let alpha = 1.0 - (progress / duration);
+                if alpha >= 0.0 {
+                    visual.color.set_a(alpha);
+                    visual.effect_color.set_a(alpha);
                 }
             }
+        }
 
+        // Sprites are drawn in depth order (within each layer, as layers are
+        // drawn separately). Equal depths are ordered by entity, which does
+        // not change from frame to frame (unlike the query order), so
+        // overlapping sprites don't flicker.
+        let mut query = world.query::<(&component::Spatial, &component::Visual)>();
+        let mut sprites: Vec<_> = query.iter().collect();
+        sort_by_depth(&mut sprites, |(entity, (_, visual))| (visual.depth, entity.to_bits().into()));
+        let num_sprites = sprites.len();
+
+        for (_entity, (spatial, visual)) in sprites {
             if let Some(ref layer) = visual.layer {
                 visual.sprite.draw_transformed(
                     &layer, visual.frame_id as u32,
@@ -98,14 +83,15 @@ impl Render {
                     spatial.angle.to_radians(), (visual.effect_scale, visual.effect_scale)
                 );
             }
+        }
+        drop(query);
 
+        for (_entity, (spatial, visual)) in world.query_mut::<(&component::Spatial, &mut component::Visual)>() {
             visual.frame_id = if visual.fps == 0 {
                 cmp::min(29, cmp::max(0, (15.0 + (15.0 * spatial.lean)) as i32)) as f32
             } else {
                 visual.frame_id + delta * visual.fps as f32
             };
-
-            num_sprites += 1;
         }
 
         self.num_frames += 1;
@@ -119,43 +105,58 @@ impl Render {
             inf.font.write(&layer, &format!("Entities: {:?}", num_sprites), (10.0, 72.0), Color::alpha_pm(0.4));
         }
 
-        // Backgrounds requested by the Itsy script (draw_background):
-        // tiled below all render layers, wrapped for infinite scrolling.
-        let (display_w, display_h) = inf.display.dimensions();
-        for draw in inf.background_draws.iter() {
-            Self::draw_background_tiled(renderer, display_w as f32, display_h as f32, draw);
-        }
+        // The frame is drawn into the HDR target and tone mapped to the display.
+        renderer.postprocess(inf.tone_map.pass(), &blendmodes::ALPHA, || {
 
-        // render layers (passes created by the Itsy script)
-        for info in inf.render_layers.iter() {
-            if let Some(layer) = inf.layers.get(info.layer_id as usize) {
-                match info.filter {
-                    Some(RenderFilter::Bloom) => {
-                        renderer.postprocess(&self.bloom, &(), || {
-                            renderer.fill().color(Color::alpha_mask(0.3)).draw();
-                            renderer.draw_layer(layer, info.component);
-                        });
-                    }
-                    Some(RenderFilter::Glare) => {
-                        renderer.postprocess(&self.glare, &blendmodes::SCREEN, || {
-                            renderer.fill().color(Color::alpha_mask(0.05)).draw();
-                            renderer.draw_layer(layer, info.component);
-                        });
-                    }
-                    None => {
-                        renderer.draw_layer(layer, info.component);
-                    }
+            // Backgrounds requested by the Itsy script (draw_background):
+            // tiled below all render layers, wrapped for infinite scrolling.
+            let (display_w, display_h) = inf.display.dimensions();
+            for draw in inf.background_draws.iter() {
+                Self::draw_background_tiled(renderer, display_w as f32, display_h as f32, draw);
+            }
+
+            // render layers (passes created by the Itsy script)
+            for info in inf.render_layers.iter() {
+                if let Some(layer) = inf.layers.get(info.layer_id as usize) {
+                    let filters: Vec<&RenderFilter> = info.filters.iter().filter_map(|&id| inf.filter(id)).collect();
+                    Self::draw_filtered(renderer, &filters, &|| Self::draw_layer(renderer, inf, info.layer_id, layer, info.component));
+                } else {
+                    eprintln!("render_layers: invalid layer id {}", info.layer_id);
                 }
-            } else {
-                eprintln!("render_layers: invalid layer id {}", info.layer_id);
             }
-        }
+        });
 
-        for layer in inf.layers.iter() {
+        for layer in inf.layers.iter().chain(inf.program_layers.values()) {
             layer.clear();
         }
     }
 
+    /// Draws a script layer through its program (set_layer_program), followed
+    /// by its entities drawn through programs (set_entity_program).
+    fn draw_layer(renderer: &Renderer, inf: &Infrastructure, layer_id: u32, layer: &Layer, component: u32) {
+        Self::draw_programmed(renderer, inf.program(inf.layer_programs[layer_id as usize]), layer, component);
+        for (&(_, program_id), program_layer) in inf.program_layers.range((layer_id, 0)..=(layer_id, u32::MAX)) {
+            Self::draw_programmed(renderer, inf.program(program_id), program_layer, component);
+        }
+    }
+
+    /// Draws a layer, through `program` if given (with the layer's blendmode).
+    fn draw_programmed(renderer: &Renderer, program: Option<&ShaderPass>, layer: &Layer, component: u32) {
+        match program {
+            Some(program) => renderer.postprocess(program, &layer.blendmode(), || renderer.draw_layer(layer, component)),
+            None => renderer.draw_layer(layer, component),
+        }
+    }
+
+    /// Draws `draw` through a filter chain: the first filter is applied first
+    /// (innermost), each following filter processes the previous one's output.
+    fn draw_filtered(renderer: &Renderer, filters: &[&RenderFilter], draw: &dyn Fn()) {
+        match filters.split_last() {
+            Some((last, rest)) => last.apply(renderer, &|| Self::draw_filtered(renderer, rest, draw)),
+            None => draw(),
+        }
+    }
+
     /// Draws `draw` tiled so it covers the entire display. The image is scaled to
     /// cover the display (aspect preserved), then repeated in both directions with
     /// the scroll offset wrapped, so any offset scrolls seamlessly.
@@ -199,9 +200,26 @@ impl Render {
     }
 }
 
+/// Sorts `items` by ascending depth (drawn first to last); `key` returns the
+/// depth and a persistent tie breaker (e.g. the entity) for equal depths.
+fn sort_by_depth<T>(items: &mut [T], key: impl Fn(&T) -> (f32, u64)) {
+    items.sort_by(|a, b| {
+        let ((depth_a, tie_a), (depth_b, tie_b)) = (key(a), key(b));
+        depth_a.total_cmp(&depth_b).then(tie_a.cmp(&tie_b))
+    });
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Render;
+    use super::{Render, sort_by_depth};
+
+    #[test]
+    fn depth_sort_breaks_ties_by_key() {
+        let mut sprites = vec![ (1.0, 5, 'a'), (0.0, 2, 'b'), (1.0, 3, 'c'), (-2.0, 9, 'd'), (0.0, 1, 'e') ];
+        sort_by_depth(&mut sprites, |&(depth, tie, _)| (depth, tie));
+        let order: String = sprites.iter().map(|&(_, _, name)| name).collect();
+        assert_eq!(order, "debca");
+    }
 
     /// Checks that every tile is fully contained in a plausible region and
     /// that the union of tiles covers the whole display.
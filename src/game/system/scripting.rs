@@ -1,8 +1,13 @@
 use crate::prelude::*;
-use crate::scripting::{Api, ScriptContext, EntityData, ApiOp, SpawnRequest};
+use crate::scripting::{self, Api, ScriptContext, EntityData, ActionState, ApiOp, SpawnRequest, ScriptBudget, ScriptStats, TextEdit, InputMode};
+use crate::bindings::{self, Bindings};
+use crate::audio::{Bus, Placement};
 use crate::game::component;
 use crate::game::{Infrastructure, State};
-use crate::game::system::render::{RenderLayer, RenderFilter, RenderBackground};
+use crate::game::system::render::{RenderLayer, RenderBackground};
+use crate::game::system::{RenderFilter, FilterKind, FilterParam, is_ancestor};
+use crate::effects::{ShaderPass, ToneMapping};
+use crate::text;
 use hecs;
 use itsy;
 
@@ -12,6 +17,12 @@ pub struct Scripting {
     /// Persistent VM so that suspend/resume works and local state survives across frames.
     vm: Option<itsy::runtime::VM<Api, ScriptContext>>,
     context: ScriptContext,
+    /// Per-frame execution limits for the VM (runaway-loop watchdog).
+    budget: ScriptBudget,
+    /// Timing and instruction count of the last frame's VM run.
+    stats: ScriptStats,
+    /// Live, recorded or replayed script input.
+    input_mode: InputMode,
 }
 
 impl Scripting {
@@ -20,19 +31,70 @@ impl Scripting {
             program: None,
             vm: None,
             context: ScriptContext::new(),
+            budget: ScriptBudget::default(),
+            stats: ScriptStats::default(),
+            input_mode: InputMode::Live,
         }
     }
 
-    /// Prepare scripting state/input prior to script processing.
-    pub fn prepare_frame(self: &mut Self, world: &mut hecs::World, inf: &mut Infrastructure, age: f32) {
+    /// Replace the per-frame execution budget.
+    pub fn set_budget(&mut self, budget: ScriptBudget) {
+        self.budget = budget;
+    }
+
+    /// Timing and instruction count of the last frame's VM run.
+    pub fn stats(&self) -> ScriptStats {
+        self.stats
+    }
+
+    /// Replace the input mode (live, recorded or replayed input).
+    pub fn set_input_mode(&mut self, mode: InputMode) {
+        self.input_mode = mode;
+    }
+
+    /// True if the input is replayed.
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.input_mode, InputMode::Play(_))
+    }
+
+    /// Recorded delta of the next replayed frame (None unless replaying, or
+    /// once the replay is over).
+    pub fn replay_delta(&self) -> Option<Duration> {
+        match &self.input_mode {
+            InputMode::Play(replay) => replay.next_delta(),
+            _ => None,
+        }
+    }
+
+    /// Take the input mode (with its recording or replay), leaving live input.
+    pub fn take_input_mode(&mut self) -> InputMode {
+        std::mem::take(&mut self.input_mode)
+    }
+
+    /// Prepare scripting state/input prior to script processing. `real_delta`
+    /// is the real time since the previous frame (recorded with the input).
+    pub fn prepare_frame(self: &mut Self, world: &mut hecs::World, inf: &mut Infrastructure, age: f32, real_delta: Duration) {
 
         self.prepare_collision_pairs(world);
-        self.prepare_keys(&inf.input);
+        if let InputMode::Play(replay) = &mut self.input_mode {
+            replay.play(&mut self.context);
+            self.context.action_bindings = Self::describe_bindings(&inf.bindings);
+        } else {
+            self.prepare_actions(&inf.input, &mut inf.bindings);
+            let text_edits = self.prepare_text_input(&inf.input);
+            self.context.mouse_pos = inf.input.mouse();
+            self.context.mouse_delta = inf.input.mouse_delta();
+            if let InputMode::Record(replay) = &mut self.input_mode {
+                replay.record(&self.context, &text_edits, real_delta);
+            }
+        }
 
         self.context.game_time = age;
-        self.context.mouse_pos = inf.input.mouse();
-        self.context.mouse_delta = inf.input.mouse_delta();
         self.context.screen_size = inf.display.dimensions();
+        for bus in Bus::ALL {
+            self.context.bus_volumes[bus.index()] = inf.audio.buses().volume(bus);
+            self.context.bus_muted[bus.index()] = inf.audio.buses().is_muted(bus);
+        }
     }
 
     /// Run the Itsy script for one frame.
@@ -40,6 +102,10 @@ impl Scripting {
         // Build entity snapshot
         self.build_snapshot(world);
 
+        // Deliver timers due this frame (timers bound to dead entities are dropped).
+        let entity_data = &self.context.entity_data;
+        self.context.timer_events = state.timers.poll(self.context.game_time, |id| entity_data.get(&id).map(|e| e.alive).unwrap_or(false));
+
         // Load and compile the script once (itsy::build also resolves `mod` declarations,
         // e.g. `mod menu;` -> res/script/menu.itsy, relative to the source file).
         if self.program.is_none() {
@@ -66,7 +132,10 @@ impl Scripting {
         // `vm.run(&mut self.context)` and the executor's `&mut self` below.
         let mut vm = self.vm.take().unwrap();
 
-        match vm.run(&mut self.context) {
+        let (result, stats) = scripting::run_budgeted(&mut vm, &mut self.context, &self.budget);
+        self.stats = stats;
+
+        match result {
             Ok(itsy::runtime::VMState::Suspended) => {
                 // Script suspended — operations recorded in context.pending
             }
@@ -74,15 +143,18 @@ impl Scripting {
                 // Script finished without suspending (shouldn't happen with while-loop).
                 // Reset and let it restart on the next frame.
                 eprintln!("Script terminated unexpectedly, resetting VM");
-                vm.reset();
+                self.reset(&mut vm, world, inf, state);
             }
             Ok(itsy::runtime::VMState::Error(_)) => {
                 eprintln!("Script error, resetting VM");
-                vm.reset();
+                self.reset(&mut vm, world, inf, state);
             }
             Err(e) => {
-                eprintln!("Script error: {:?}, resetting VM", e);
-                vm.reset();
+                // An interrupted frame may have queued an unbounded number of
+                // operations; they are dropped with the rest of the script state
+                // instead of executing a partial frame.
+                eprintln!("Script error: {}, resetting VM", e);
+                self.reset(&mut vm, world, inf, state);
             }
         }
 
@@ -102,6 +174,18 @@ impl Scripting {
         }
     }
 
+    /// Reset the VM so the script restarts at `main` next frame, and drop
+    /// everything the previous run created: its entities, timers, render
+    /// resources and the operations it queued. Otherwise the restarted `main`
+    /// would create all of them a second time next to the old ones.
+    fn reset(&mut self, vm: &mut itsy::runtime::VM<Api, ScriptContext>, world: &mut hecs::World, inf: &mut Infrastructure, state: &mut State) {
+        vm.reset();
+        world.clear();
+        state.timers.clear();
+        inf.clear_script_resources();
+        self.context.reset_script_state();
+    }
+
     /// Execute one API operation recorded during vm.run() (see ApiOp).
     fn execute_command(&mut self, world: &mut hecs::World, cmd: &mut hecs::CommandBuffer, inf: &mut Infrastructure, state: &mut State, op: ApiOp) {
         match op {
@@ -115,22 +199,136 @@ impl Scripting {
                 }
                 inf.layers.push(layer);
                 inf.layer_scales.push(scale);
+                inf.layer_programs.push(Api::PROGRAM_NONE);
             }
-            ApiOp::AddRenderLayer { layer_id, filter, component } => {
-                inf.render_layers.push(RenderLayer {
-                    layer_id,
-                    filter: match filter {
-                        Api::FILTER_BLOOM => Some(RenderFilter::Bloom),
-                        Api::FILTER_GLARE => Some(RenderFilter::Glare),
-                        _ => None,
-                    },
-                    component,
+            ApiOp::AddRenderLayer { layer_id, filter_id, component } => {
+                let filters = if filter_id == Api::FILTER_NONE { Vec::new() } else { vec![ filter_id ] };
+                inf.render_layers.push(RenderLayer { layer_id, filters, component });
+            }
+            ApiOp::AddPassFilter { pass_id, filter_id } => {
+                match inf.render_layers.get_mut(pass_id as usize) {
+                    // a filter renders into its own targets, so it can't process its own output
+                    Some(pass) if pass.filters.contains(&filter_id) => eprintln!("add_pass_filter: filter {} is already in the chain of pass {}", filter_id, pass_id),
+                    Some(pass) => pass.filters.push(filter_id),
+                    None => eprintln!("add_pass_filter: invalid pass id {}", pass_id),
+                }
+            }
+            ApiOp::CreateFilter { kind } => {
+                let kind = FilterKind::from_id(kind).unwrap_or_else(|| {
+                    eprintln!("create_filter: invalid kind {}, using bloom", kind);
+                    FilterKind::Bloom
                 });
+                // always created, so the IDs returned to the script stay in sync
+                inf.filters.push(RenderFilter::new(&inf.display.context(), inf.display.dimensions(), kind));
+            }
+            ApiOp::SetFilterParam { filter_id, param, value } => {
+                let (context, dimensions) = (inf.display.context(), inf.display.dimensions());
+                let Some(filter) = inf.filters.get_mut((filter_id as usize).wrapping_sub(1)) else {
+                    eprintln!("set_filter_param: invalid filter id {}", filter_id);
+                    return;
+                };
+                let Some(param) = FilterParam::from_id(param) else {
+                    eprintln!("set_filter_param: invalid param {}", param);
+                    return;
+                };
+                if let Err(e) = filter.set_param(&context, dimensions, param, value) {
+                    eprintln!("set_filter_param: {}", e);
+                }
             }
-            ApiOp::WriteText { layer_id, msg, x, y, alpha, menu } => {
-                if let Some(layer) = inf.layers.get(layer_id as usize) {
-                    let font = if menu { &inf.menu_font } else { &inf.font };
-                    font.write(layer, &msg, (x, y), Color::alpha_pm(alpha));
+            ApiOp::SetFilterLut { filter_id, lut_id } => {
+                let name = &self.context.lut_list[lut_id as usize];
+                let Some(filter) = inf.filters.get_mut((filter_id as usize).wrapping_sub(1)) else {
+                    eprintln!("set_filter_lut: invalid filter id {}", filter_id);
+                    return;
+                };
+                match Texture::from_file(&inf.display.context(), name) {
+                    Ok(lut) => if let Err(e) = filter.set_lut(lut) {
+                        eprintln!("set_filter_lut: {}", e);
+                    },
+                    Err(e) => eprintln!("set_filter_lut: failed to load '{}': {:?}", name, e),
+                }
+            }
+            ApiOp::SetExposure(exposure) => {
+                inf.tone_map.set_exposure(exposure);
+            }
+            ApiOp::SetToneMapping(mapping) => {
+                match ToneMapping::from_id(mapping) {
+                    Some(mapping) => inf.tone_map.set_mapping(mapping),
+                    None => eprintln!("set_tone_mapping: invalid mapping {}", mapping),
+                }
+            }
+            ApiOp::CreateProgram { shader_id } => {
+                let id = inf.programs.len() as u32 + 1;
+                let program = match self.context.shader_list.get(shader_id as usize) {
+                    Some(name) => fs::read_to_string(name)
+                        .map_err(|e| format!("cannot read '{}': {}", name, e))
+                        .and_then(|source| ShaderPass::new(&inf.display.context(), inf.display.dimensions(), &source))
+                        .map_err(|e| format!("{}: {}", name, e)),
+                    None => Err(format!("invalid shader id {}", shader_id)),
+                };
+                // always pushed, so the IDs returned to the script stay in sync
+                match program {
+                    Ok(program) => inf.programs.push(Some(program)),
+                    Err(e) => {
+                        eprintln!("create_program: {}", e);
+                        self.context.program_errors.insert(id, e);
+                        inf.programs.push(None);
+                    }
+                }
+            }
+            ApiOp::SetProgramUniform { program_id, name, value } => {
+                match inf.program(program_id) {
+                    Some(program) => program.set_float(&name, value),
+                    None => eprintln!("set_program_uniform: invalid program id {}", program_id),
+                }
+            }
+            ApiOp::SetLayerProgram { layer_id, program_id } => {
+                match inf.layer_programs.get_mut(layer_id as usize) {
+                    Some(layer_program) => *layer_program = program_id,
+                    None => eprintln!("set_layer_program: invalid layer id {}", layer_id),
+                }
+            }
+            ApiOp::SetEntityProgram { entity_id, program_id } => {
+                if let Some(entity) = hecs::Entity::from_bits(entity_id) {
+                    if let Ok(mut visual) = world.get::<&mut component::Visual>(entity) {
+                        if let Some(layer) = visual.layer.clone() {
+                            visual.layer = inf.program_layer(&layer, program_id).or(Some(layer));
+                        }
+                        if let Some(effect_layer) = visual.effect_layer.clone() {
+                            visual.effect_layer = inf.program_layer(&effect_layer, program_id).or(Some(effect_layer));
+                        }
+                    }
+                }
+            }
+            ApiOp::CreateFont { font_file, size } => {
+                let id = inf.fonts.len() as u32 + 1;
+                let path = &self.context.font_list[font_file as usize];
+                // always pushed, so the IDs returned to the script stay in sync
+                match text::load_font(&inf.display.context(), path, size) {
+                    Ok(font) => {
+                        let font = font.arc();
+                        self.context.fonts.insert(id, font.clone());
+                        inf.fonts.push(Some(font));
+                    }
+                    Err(e) => {
+                        eprintln!("create_font: {}", e);
+                        inf.fonts.push(None);
+                    }
+                }
+            }
+            ApiOp::WriteText { layer_id, font_id, msg, position, align, max_width, color } => {
+                let Some(layer) = inf.layers.get(layer_id as usize) else {
+                    eprintln!("write_text: invalid layer id {}", layer_id);
+                    return;
+                };
+                let Some(font) = inf.font(font_id) else {
+                    eprintln!("write_text: invalid font id {}", font_id);
+                    return;
+                };
+                let align = (text::HAlign::from_id(align.0), text::VAlign::from_id(align.1));
+                let color = Color(color.0, color.1, color.2, color.3).to_pm();
+                for (line, position) in text::layout(&msg, position, align, max_width, |line| font.measure(line)) {
+                    font.write(layer, &line, position, color);
                 }
             }
             ApiOp::SetDebugLayer(layer_id) => {
@@ -138,9 +336,14 @@ impl Scripting {
             }
             ApiOp::PauseTime => {
                 state.timeframe.lerp_rate(0.0, Duration::from_millis(500));
+                inf.audio.set_game_time(state.timeframe.elapsed(), state.timeframe.rate() as f32, state.timeframe.target_rate() as f32);
             }
             ApiOp::ResumeTime => {
                 state.timeframe.lerp_rate(1.0, Duration::from_millis(500));
+                inf.audio.set_game_time(state.timeframe.elapsed(), state.timeframe.rate() as f32, state.timeframe.target_rate() as f32);
+            }
+            ApiOp::SaveSettings => {
+                inf.save_settings();
             }
             ApiOp::RequestExit => {
                 state.exit_requested = true;
@@ -152,6 +355,23 @@ impl Scripting {
                 eprintln!("[debug] SetResolution op: ({width}, {height})");
                 state.resolution_requested = Some((width, height));
             }
+            ApiOp::TakeScreenshot { overlay } => {
+                state.screenshot_requested = Some(overlay);
+            }
+            ApiOp::GrabCursor(grab) => {
+                if grab {
+                    inf.display.grab_cursor();
+                } else {
+                    inf.display.free_cursor();
+                }
+            }
+            ApiOp::SetCursorVisible(visible) => {
+                if visible {
+                    inf.display.show_cursor();
+                } else {
+                    inf.display.hide_cursor();
+                }
+            }
             ApiOp::ToggleFullscreen => {
                 eprintln!("[debug] ToggleFullscreen op, state.fullscreen = {}", state.fullscreen);
                 if state.fullscreen {
@@ -181,16 +401,74 @@ impl Scripting {
                     eprintln!("[debug] ToggleFullscreen: -> fullscreen");
                 }
             }
-            ApiOp::PlaySound { id } => {
-                let name = self.context.sound_list[id as usize].clone();
-                if inf.sound_cache.get(&name).is_none() {
-                    match crate::sound::Sound::load(&name) {
-                        Ok(sound) => { inf.sound_cache.insert(name.clone(), sound); }
-                        Err(e) => { eprintln!("play_sound: failed to load '{}': {}", name, e); return; }
+            ApiOp::PlaySound { id, bus } => {
+                let name = &self.context.sound_list[id as usize];
+                let bus = Bus::from_id(bus).unwrap_or(Bus::Sfx);
+                if let Err(e) = inf.audio.play(name, bus, Placement::Center) {
+                    eprintln!("play_sound: failed to load '{}': {}", name, e);
+                }
+            }
+            ApiOp::PreloadSound(id) => {
+                let name = &self.context.sound_list[id as usize];
+                if let Err(e) = inf.audio.preload(name) {
+                    eprintln!("preload_sound: failed to load '{}': {}", name, e);
+                }
+            }
+            ApiOp::PlaySoundAt { id, x, y, entity_id } => {
+                let name = &self.context.sound_list[id as usize];
+                let placement = if entity_id != 0 { Placement::Entity(entity_id, Vec2(x, y)) } else { Placement::At(Vec2(x, y)) };
+                if let Err(e) = inf.audio.play(name, Bus::Sfx, placement) {
+                    eprintln!("play_sound_at: failed to load '{}': {}", name, e);
+                }
+            }
+            ApiOp::PlayGroup { name, position } => {
+                let placement = position.map(|(x, y)| Placement::At(Vec2(x, y))).unwrap_or(Placement::Center);
+                if let Err(e) = inf.audio.play_group(&name, placement) {
+                    eprintln!("play_group: {}", e);
+                }
+            }
+            ApiOp::PreloadGroup(name) => {
+                let files = inf.audio.group_files(&name);
+                if files.is_empty() {
+                    eprintln!("preload_group: unknown sound group '{}'", name);
+                }
+                for file in files {
+                    if let Err(e) = inf.audio.preload(&file) {
+                        eprintln!("preload_group: failed to load '{}': {}", file, e);
                     }
                 }
-                let sound = inf.sound_cache.get(&name).unwrap();
-                inf.audio.add(sound.decoder());
+            }
+            ApiOp::SetListener(position) => {
+                inf.audio.set_listener(position.map(|(x, y)| Vec2(x, y)));
+            }
+            ApiOp::PlayMusic { id, crossfade, looping } => {
+                inf.audio.music.play(&self.context.music_list[id as usize], crossfade, looping);
+            }
+            ApiOp::QueueMusic(id) => {
+                inf.audio.music.queue(&self.context.music_list[id as usize]);
+            }
+            ApiOp::SetMusicLoop(looping) => {
+                inf.audio.music.set_looping(looping);
+            }
+            ApiOp::SetMusicPauseWithGame(pause_with_game) => {
+                inf.audio.music.set_pause_with_game(pause_with_game);
+            }
+            ApiOp::StopMusic { fade } => {
+                inf.audio.music.stop(fade);
+            }
+            ApiOp::SetBusVolume { bus, volume } => {
+                if let Some(bus) = Bus::from_id(bus) {
+                    inf.audio.buses().set_volume(bus, volume);
+                    inf.settings.audio.bus_mut(bus).volume = volume;
+                    inf.settings_dirty = true;
+                }
+            }
+            ApiOp::SetBusMuted { bus, muted } => {
+                if let Some(bus) = Bus::from_id(bus) {
+                    inf.audio.buses().set_muted(bus, muted);
+                    inf.settings.audio.bus_mut(bus).muted = muted;
+                    inf.settings_dirty = true;
+                }
             }
             ApiOp::DrawBackground { id, offset_x, offset_y } => {
                 let name = self.context.background_list[id as usize].clone();
@@ -239,6 +517,13 @@ impl Scripting {
                     }
                 }
             }
+            ApiOp::SetDepth { id, depth } => {
+                if let Some(entity) = hecs::Entity::from_bits(id) {
+                    if let Ok(mut visual) = world.get::<&mut component::Visual>(entity) {
+                        visual.depth = depth;
+                    }
+                }
+            }
             ApiOp::SetHitpoints { id, hp } => {
                 if let Some(entity) = hecs::Entity::from_bits(id) {
                     if let Ok(mut hitpoints) = world.get::<&mut component::Hitpoints>(entity) {
@@ -253,6 +538,48 @@ impl Scripting {
                     }
                 }
             }
+            ApiOp::RebindAction(action) => {
+                self.context.rebinding = Some(action);
+            }
+            ApiOp::ResetBindings => {
+                match Bindings::load_file(bindings::DEFAULT_PATH) {
+                    Ok(defaults) => {
+                        inf.bindings = defaults;
+                        if let Err(e) = inf.bindings.save() {
+                            eprintln!("reset_bindings: cannot save '{}': {}", bindings::USER_PATH, e);
+                        }
+                    }
+                    Err(e) => eprintln!("reset_bindings: cannot load '{}': {}", bindings::DEFAULT_PATH, e),
+                }
+            }
+            ApiOp::StartTimer { id, delay, interval, entity_id, game_time } => {
+                state.timers.start(id, game_time, delay, interval, entity_id);
+            }
+            ApiOp::CancelTimer(id) => {
+                state.timers.cancel(id);
+            }
+            ApiOp::Attach { child, parent, offset_x, offset_y, angle, inherit_angle, despawn_with_parent } => {
+                if let (Some(child_entity), Some(parent_entity)) = (hecs::Entity::from_bits(child), hecs::Entity::from_bits(parent)) {
+                    if !world.contains(parent_entity) {
+                        eprintln!("attach_entity(child={}, parent={}): parent does not exist", child, parent);
+                        return;
+                    }
+                    if is_ancestor(world, child_entity, parent_entity) {
+                        eprintln!("attach_entity(child={}, parent={}): the child is an ancestor of the parent", child, parent);
+                        return;
+                    }
+                    let parent_component = component::Parent { entity: parent_entity, inherit_angle, despawn_with_parent };
+                    let local = component::LocalTransform { position: Vec2(offset_x, offset_y), angle: Angle(angle) };
+                    if let Err(e) = world.insert(child_entity, (parent_component, local)) {
+                        eprintln!("attach_entity(child={}, parent={}) failed: {:?}", child, parent, e);
+                    }
+                }
+            }
+            ApiOp::Detach(child) => {
+                if let Some(entity) = hecs::Entity::from_bits(child) {
+                    let _ = world.remove::<(component::Parent, component::LocalTransform)>(entity);
+                }
+            }
         }
     }
 
@@ -315,7 +642,7 @@ impl Scripting {
             entity_type, sprite_id, layer_id, effect_layer_id,
             px, py, angle, vx, vy, faction,
             hitpoints, radius, lifetime, fade, fps,
-            color_r, color_g, color_b, game_time,
+            color_r, color_g, color_b, depth, game_time,
         } = req;
 
         let mut builder = hecs::EntityBuilder::new();
@@ -432,43 +759,63 @@ impl Scripting {
             effect_color: Color::WHITE,
             frame_id: 0.0,
             fps: fps,
+            depth: depth,
         });
 
         cmd.spawn(builder.build());
     }
 
-    /// Identifies pressed keys.
-    fn prepare_keys(self: &mut Self, input: &Input) {
-
-        // Input masks: one bit per key (see KEY_* in scripting/mod.rs).
-        // `keys` = held down, `pressed` = pressed this frame (incl. repeats),
-        // `edge` = initial press this frame (no repeats).
-        let (keys, pressed, edge) = {
-            let mut keys = 0u16;
-            let mut pressed = 0u16;
-            let mut edge = 0u16;
-            let mut add_key = |key: InputId, bit: u16| {
-                if input.down(key) { keys |= bit; }
-                if input.pressed(key, true) { pressed |= bit; }
-                if input.pressed(key, false) { edge |= bit; }
-            };
-            add_key(InputId::W, Api::KEY_W);
-            add_key(InputId::S, Api::KEY_S);
-            add_key(InputId::A, Api::KEY_A);
-            add_key(InputId::D, Api::KEY_D);
-            add_key(InputId::RShift, Api::KEY_RSHIFT);
-            add_key(InputId::CursorUp, Api::KEY_CURSOR_UP);
-            add_key(InputId::CursorDown, Api::KEY_CURSOR_DOWN);
-            add_key(InputId::Return, Api::KEY_RETURN);
-            add_key(InputId::Escape, Api::KEY_ESCAPE);
-            add_key(InputId::Mouse1, Api::KEY_MOUSE1);
-            add_key(InputId::LControl, Api::KEY_LCONTROL);
-            (keys, pressed, edge)
-        };
+    /// Updates the named input actions from the bound inputs, or, while an
+    /// action is being rebound, captures the next pressed input as its binding.
+    fn prepare_actions(self: &mut Self, input: &Input, bindings: &mut Bindings) {
+
+        self.context.actions.clear();
+
+        if let Some(action) = self.context.rebinding.clone() {
+            // All actions read as released while waiting for the new binding.
+            // Escape (not bindable) cancels.
+            if input.pressed(InputId::Escape, false) {
+                self.context.rebinding = None;
+            } else if let Some(&key) = bindings::BINDABLE.iter().find(|&&key| input.pressed(key, false)) {
+                self.context.rebinding = None;
+                bindings.bind(&action, vec![ key ]);
+                if let Err(e) = bindings.save() {
+                    eprintln!("rebind_action: cannot save '{}': {}", bindings::USER_PATH, e);
+                }
+            }
+        } else if self.context.text_input.is_none() {
+            // (while a text input is active, all actions read as released)
+            for (action, inputs) in bindings.actions.iter() {
+                let mut state = ActionState::default();
+                for &key in inputs.iter() {
+                    state.down |= input.down(key);
+                    state.pressed |= input.pressed(key, true);
+                    state.edge |= input.pressed(key, false);
+                }
+                self.context.actions.insert(action.clone(), state);
+            }
+        }
 
-        self.context.input_keys = keys;
-        self.context.input_pressed = pressed;
-        self.context.input_edge = edge;
+        self.context.action_bindings = Self::describe_bindings(bindings);
+    }
+
+    /// Human readable binding of each action.
+    fn describe_bindings(bindings: &Bindings) -> HashMap<String, String> {
+        bindings.actions.keys().map(|action| (action.clone(), bindings.describe(action))).collect()
+    }
+
+    /// Feeds the keys typed this frame to the active text input (if any) and
+    /// returns the edits.
+    fn prepare_text_input(self: &mut Self, input: &Input) -> Vec<TextEdit> {
+        let Some(text_input) = &mut self.context.text_input else {
+            return Vec::new();
+        };
+        let edits = text_edits(input);
+        text_input.clear_events();
+        for &edit in edits.iter() {
+            text_input.apply(edit);
+        }
+        edits
     }
 
     /// Detect collision pairs for the scripting subsystem.
@@ -494,3 +841,25 @@ impl Scripting {
         self.context.collisions = pairs;
     }
 }
+
+/// Editing keys of a text input.
+const TEXT_EDIT_KEYS: &[(InputId, TextEdit)] = &[
+    (InputId::Backspace, TextEdit::Backspace), (InputId::Delete, TextEdit::Delete),
+    (InputId::CursorLeft, TextEdit::Left), (InputId::CursorRight, TextEdit::Right),
+    (InputId::Home, TextEdit::Home), (InputId::End, TextEdit::End),
+    (InputId::Return, TextEdit::Submit), (InputId::Escape, TextEdit::Cancel),
+];
+
+/// Text input events of this frame, in the order the window received them:
+/// the characters typed (with the player's keyboard layout, dead keys and IME
+/// applied) and the editing keys it reports as control characters. Editing
+/// keys without a character (caret keys, and keys the platform doesn't report
+/// as one) follow them, if pressed (including key repeat).
+fn text_edits(input: &Input) -> Vec<TextEdit> {
+    let mut edits = TextEdit::from_text(&input.text());
+    let reported = edits.clone();
+    edits.extend(TEXT_EDIT_KEYS.iter()
+        .filter(|(key, edit)| input.pressed(*key, true) && !reported.contains(edit))
+        .map(|&(_, edit)| edit));
+    edits
+}
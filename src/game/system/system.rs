@@ -1,6 +1,12 @@
 mod inertia;
 pub use self::inertia::run as run_inertia;
 
+mod hierarchy;
+pub use self::hierarchy::{run as run_hierarchy, is_ancestor};
+
+mod filter;
+pub use self::filter::{RenderFilter, FilterKind, FilterParam};
+
 mod render;
 pub use self::render::{Render, RenderLayer, RenderBackground};
 
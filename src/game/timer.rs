@@ -0,0 +1,133 @@
+/// A scheduled timer (see `Timers`).
+#[derive(Clone, Debug)]
+struct Timer {
+    /// Timer ID shared between Itsy and Rust (allocated by the API, never 0).
+    id          : u32,
+    /// Game time at which the timer fires next.
+    next        : f32,
+    /// Repeat interval in seconds, 0 = one-shot.
+    interval    : f32,
+    /// Entity the timer is bound to (cancelled when it dies), 0 = unbound.
+    entity_id   : u64,
+}
+
+/// Timer service on game time: one-shot and repeating timers, optionally bound to
+/// an entity. Firings are polled once per frame and delivered to the Itsy script
+/// as (timer_id, entity_id) events. Since timers run on game time, they follow
+/// pause and time rate changes.
+#[derive(Default)]
+pub struct Timers {
+    timers: Vec<Timer>,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers {
+            timers: Vec::new(),
+        }
+    }
+
+    /// Start (or restart) timer `id`, first firing `delay` seconds after game time `now`,
+    /// then every `interval` seconds (0 = one-shot).
+    pub fn start(&mut self, id: u32, now: f32, delay: f32, interval: f32, entity_id: u64) {
+        self.cancel(id);
+        self.timers.push(Timer {
+            id          : id,
+            next        : now + delay.max(0.0),
+            interval    : interval.max(0.0),
+            entity_id   : entity_id,
+        });
+    }
+
+    /// Cancel timer `id` (no-op if it does not exist or already fired).
+    pub fn cancel(&mut self, id: u32) {
+        self.timers.retain(|timer| timer.id != id);
+    }
+
+    /// Cancel all timers (e.g. when the script restarts).
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+
+    /// True if no timers are pending.
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Collect the timers due at game time `now`, as (timer_id, entity_id) pairs
+    /// ordered by due time. Timers bound to entities that are no longer alive
+    /// are dropped without firing. A repeating timer fires at most once per
+    /// poll; its next firing catches up missed intervals like `Periodic` does
+    /// (next = now + interval - min(now - due, interval)).
+    pub fn poll(&mut self, now: f32, is_alive: impl Fn(u64) -> bool) -> Vec<(u32, u64)> {
+        self.timers.retain(|timer| timer.entity_id == 0 || is_alive(timer.entity_id));
+
+        let mut due: Vec<(f32, u32, u64)> = Vec::new();
+
+        self.timers.retain_mut(|timer| {
+            if now < timer.next {
+                return true;
+            }
+            due.push((timer.next, timer.id, timer.entity_id));
+            if timer.interval > 0.0 {
+                let overshoot = (now - timer.next).min(timer.interval);
+                timer.next = now + timer.interval - overshoot;
+                true
+            } else {
+                false
+            }
+        });
+
+        due.sort_by(|a, b| a.0.total_cmp(&b.0));
+        due.into_iter().map(|(_, id, entity_id)| (id, entity_id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timers;
+
+    #[test]
+    fn one_shot_fires_once() {
+        let mut timers = Timers::new();
+        timers.start(1, 0.0, 2.0, 0.0, 0);
+        assert!(timers.poll(1.9, |_| true).is_empty());
+        assert_eq!(timers.poll(2.0, |_| true), vec![(1, 0)]);
+        assert!(timers.poll(5.0, |_| true).is_empty());
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn repeating_timer_catches_up_overshoot() {
+        let mut timers = Timers::new();
+        timers.start(7, 0.0, 4.0, 2.0, 0);
+        assert_eq!(timers.poll(4.5, |_| true), vec![(7, 0)]);
+        // next = 4.5 + 2.0 - 0.5 = 6.0
+        assert!(timers.poll(5.9, |_| true).is_empty());
+        assert_eq!(timers.poll(6.0, |_| true), vec![(7, 0)]);
+        // after a long hitch, the missed firing is caught up on the next poll
+        assert_eq!(timers.poll(20.0, |_| true), vec![(7, 0)]);
+        assert_eq!(timers.poll(20.5, |_| true), vec![(7, 0)]);
+        assert!(timers.poll(21.9, |_| true).is_empty());
+        assert_eq!(timers.poll(22.0, |_| true), vec![(7, 0)]);
+    }
+
+    #[test]
+    fn entity_timers_are_cancelled_on_death() {
+        let mut timers = Timers::new();
+        timers.start(1, 0.0, 1.0, 0.0, 42);
+        timers.start(2, 0.0, 1.0, 0.0, 43);
+        assert_eq!(timers.poll(1.0, |id| id != 42), vec![(2, 43)]);
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn events_are_ordered_by_due_time_and_cancel_works() {
+        let mut timers = Timers::new();
+        timers.start(1, 0.0, 3.0, 0.0, 0);
+        timers.start(2, 0.0, 1.0, 0.0, 0);
+        timers.start(3, 0.0, 2.0, 0.0, 0);
+        timers.cancel(3);
+        assert_eq!(timers.poll(3.0, |_| true), vec![(2, 0), (1, 0)]);
+    }
+}
@@ -0,0 +1,22 @@
+//! Spacegame library: the game (ECS world, systems, Itsy scripting) without the
+//! window/main loop, so integration tests and tools can embed it. The `spacegame`
+//! binary (src/main.rs) is a thin main loop on top of it.
+
+extern crate radiant_rs as radiant;
+
+pub mod prelude;
+pub mod sound;
+pub mod bindings;
+pub mod settings;
+#[path="audio/audio.rs"]
+pub mod audio;
+#[path="game/game.rs"]
+pub mod game;
+pub mod bloom;
+pub mod effects;
+pub mod offline;
+pub mod screenshot;
+pub mod text;
+pub mod timeframe;
+#[path="scripting/scripting.rs"]
+pub mod scripting;
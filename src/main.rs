@@ -1,35 +1,63 @@
-extern crate radiant_rs as radiant;
-
-mod prelude;
-mod sound;
-#[path="game/game.rs"]
-mod game;
-mod bloom;
-mod timeframe;
-#[path="scripting/scripting.rs"]
-mod scripting;
-
-use crate::prelude::*;
-use crate::game::Game;
-use rodio::DeviceSinkBuilder;
+use spacegame::prelude::*;
+use spacegame::game::Game;
+use spacegame::timeframe::Timeframe;
+use spacegame::audio::Output;
+use spacegame::offline::{self, Options};
+use spacegame::scripting::{InputMode, InputReplay};
+use spacegame::screenshot::{self, Overlay};
+use spacegame::text;
 
 fn main() {
 
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, offline::USAGE);
+        std::process::exit(2);
+    });
+    let input_mode = match (&options.replay, &options.record) {
+        (Some(path), _) => InputMode::Play(InputReplay::load(path).unwrap_or_else(|e| {
+            eprintln!("replay: {}", e);
+            std::process::exit(1);
+        })),
+        (None, Some(_)) => InputMode::Record(InputReplay::default()),
+        (None, None) => InputMode::Live,
+    };
+
     let display = Arc::new(Display::builder().dimensions((1280, 720)).vsync().build().unwrap());
-    display.grab_cursor();
+    // The cursor mode (grabbed while playing, free in the menu) is set by the
+    // Itsy script (grab_cursor / release_cursor).
     // Starts windowed: the monitor list is only available after the first event
     // pump, so fullscreen is entered via the menu (toggle_fullscreen) instead.
     let fullscreen = false;
     eprintln!("[debug] main: display created, dimensions = {:?}, fullscreen = {}", display.dimensions(), fullscreen);
     let renderer =  Renderer::new(&display).unwrap();
+    let input = Input::new(&display);
+
+    if let Some(dir) = &options.offline {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("offline: cannot create '{}': {}", dir, e);
+            std::process::exit(1);
+        }
+        let audio_path = offline::audio_path(dir);
+        let mut audio_output = Output::wav_offline(&audio_path.to_string_lossy()).unwrap_or_else(|e| {
+            eprintln!("offline: {}", e);
+            std::process::exit(1);
+        });
+        let mut game = Game::new(&input, display.clone(), fullscreen, audio_output.mixer());
+        game.simulate_time();
+        game.set_input_mode(input_mode);
+        render_offline(&display, &renderer, &input, &mut audio_output, game, &options);
+        return;
+    }
+
     let (w, h) = display.dimensions();
     let mut debug_layer = Layer::new((w as f32, h as f32));
-    let debug_font = Font::builder(&display.context()).family("Arial").size(20.0).build().unwrap().arc();
-    let input = Input::new(&display);
-    let mut audio_sink = DeviceSinkBuilder::open_default_sink().unwrap();
-    audio_sink.log_on_drop(false);
-    let audio = audio_sink.mixer().clone();
+    let debug_font = text::load_font(&display.context(), text::DEFAULT_FONT, 20.0).unwrap().arc();
+    // falls back to a silent output without a sound device (see Output::open)
+    let audio_output = Output::open();
+    eprintln!("[debug] main: audio output = {}", audio_output.name());
+    let audio = audio_output.mixer().clone();
     let mut game = Game::new(&input, display.clone(), fullscreen, &audio);
+    game.set_input_mode(input_mode);
 
     // game main loop
 
@@ -39,6 +67,12 @@ fn main() {
 
         display.poll_events();
 
+        // a replay runs on simulated time: each frame advances it by the
+        // recorded delta (by the real one once the replay is over)
+        if game.is_replaying() {
+            game.advance_time(game.replay_delta().unwrap_or(Duration::from_secs_f32(frame.delta_f32)));
+        }
+
         // ingame time and delta
 
         let age = game.game_age();
@@ -48,9 +82,23 @@ fn main() {
 
         display.clear_frame(Color::BLACK);
 
+        // A screenshot (requested by the Itsy script last frame) composes the
+        // frame into a texture, which is then copied to the display and saved.
+        let screenshot_request = game.take_screenshot_request();
+        let overlay = Overlay::for_request(screenshot_request);
+        game.set_debug_text(overlay.game_text);
+        let capture = screenshot_request.map(|_| {
+            let texture = Texture::builder(&display.context()).dimensions(display.dimensions()).build().unwrap();
+            texture.clear(Color::BLACK);
+            texture
+        });
+
         // menu handling (open/close, input, actions) lives in the Itsy script.
 
-        game.process(&renderer, age as f32, delta as f32);
+        match &capture {
+            Some(texture) => renderer.render_to(texture, || game.process(&renderer, age as f32, delta as f32)),
+            None => game.process(&renderer, age as f32, delta as f32),
+        }
 
         debug_font.write(&debug_layer, &format!("Renderer\nFPS: {}\nDelta: {:.4}", frame.fps, frame.delta_f32), (10.0, 10.0), Color::alpha_pm(0.4));
         debug_font.write(&debug_layer,
@@ -59,7 +107,28 @@ fn main() {
             Color::alpha_pm(0.4)
         );
 
-        renderer.draw_layer(&debug_layer, 0);
+        let script = game.script_stats();
+        debug_font.write(&debug_layer,
+            &format!("Script\nTime: {:.2} ms\nInstructions: {}{}",
+                Timeframe::duration_to_secs(script.elapsed) * 1000.0, script.instructions,
+                if script.interrupted { "\nINTERRUPTED" } else { "" }),
+            (10.0, 270.0),
+            Color::alpha_pm(0.4)
+        );
+
+        if let Some(texture) = &capture {
+            if overlay.capture {
+                renderer.render_to(texture, || renderer.draw_layer(&debug_layer, 0));
+            }
+            renderer.copy_from(texture, TextureFilter::Nearest);
+            match screenshot::save(texture) {
+                Ok(path) => eprintln!("screenshot saved to {}", path.display()),
+                Err(e) => eprintln!("screenshot failed: {}", e),
+            }
+        }
+        if overlay.display {
+            renderer.draw_layer(&debug_layer, 0);
+        }
         debug_layer.clear();
 
         display.swap_frame();
@@ -73,13 +142,88 @@ fn main() {
         }
 
         // the Itsy script can request a level restart (menu "New Game" /
-        // "Exit to Menu"); rebuild the level and keep running.
+        // "Exit to Menu"); rebuild the level and keep running. The input
+        // recording or replay continues into the new level.
         if game.restart_requested() {
+            game.save_settings();
             let fullscreen_now = game.is_fullscreen();
+            let input_mode = game.take_input_mode();
             game = Game::new(&input, display.clone(), fullscreen_now, &audio);
+            game.set_input_mode(input_mode);
             last_age = 0.;
         }
 
         !display.was_closed() && !game.exit_requested()
     });
+    game.save_settings();
+
+    if let (Some(path), InputMode::Record(replay)) = (&options.record, game.take_input_mode()) {
+        match replay.save(path) {
+            Ok(()) => eprintln!("input recorded to {} ({} frames)", path, replay.frames.len()),
+            Err(e) => eprintln!("recording failed: {}", e),
+        }
+    }
+}
+
+/// Run `game` for the requested number of frames at a fixed delta on its
+/// simulated clock, writing each composed frame (without the debug overlay)
+/// to a numbered PNG and the audio to a WAV file of the same length. Replayed
+/// frames use their recorded delta instead, so the input arrives at the same
+/// game time as when it was recorded.
+fn render_offline(display: &Arc<Display>, renderer: &Renderer, input: &Input, audio_output: &mut Output, mut game: Game, options: &Options) {
+    let dir = options.offline.as_deref().unwrap_or_default();
+    let create_texture = || Texture::builder(&display.context()).dimensions(display.dimensions()).build().unwrap();
+    let mut texture = create_texture();
+    let mut last_age = 0.;
+
+    for frame in 1..=options.frames {
+
+        display.poll_events();
+
+        let age = game.game_age();
+        let age_delta = age - last_age;
+        last_age = age;
+
+        // the frame is always captured, without debug text; screenshot requests
+        // have nothing to add
+        game.take_screenshot_request();
+        game.set_debug_text(false);
+        display.clear_frame(Color::BLACK);
+        texture.clear(Color::BLACK);
+        renderer.render_to(&texture, || game.process(renderer, age as f32, age_delta as f32));
+        renderer.copy_from(&texture, TextureFilter::Nearest);
+        display.swap_frame();
+
+        if let Err(e) = screenshot::write_png(&offline::frame_path(dir, frame), texture.dimensions(), &texture.read_pixels()) {
+            eprintln!("offline: frame {} failed: {}", frame, e);
+            return;
+        }
+        let delta = game.replay_delta().unwrap_or_else(|| options.delta());
+        if let Err(e) = audio_output.render(delta) {
+            eprintln!("offline: audio failed: {}", e);
+            return;
+        }
+        game.advance_time(delta);
+
+        if let Some((w, h)) = game.take_resolution_request() {
+            game.apply_resolution(w, h);
+            texture = create_texture();
+        }
+        if game.restart_requested() {
+            game.save_settings();
+            let fullscreen_now = game.is_fullscreen();
+            let input_mode = game.take_input_mode();
+            game = Game::new(input, display.clone(), fullscreen_now, audio_output.mixer());
+            game.simulate_time();
+            game.set_input_mode(input_mode);
+            last_age = 0.;
+        }
+        if display.was_closed() || game.exit_requested() {
+            game.save_settings();
+            eprintln!("offline: stopped after {} frames", frame);
+            return;
+        }
+    }
+    game.save_settings();
+    eprintln!("offline: rendered {} frames to {}", options.frames, dir);
 }
@@ -0,0 +1,111 @@
+use crate::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Command line usage of the options below.
+pub const USAGE: &str = "usage: spacegame [--offline <dir> --frames <count> [--fps <rate>]] [--replay <file>] [--record <file>]";
+
+/// Default frame rate of offline rendering.
+const DEFAULT_FPS: u32 = 60;
+
+/// Command line options: offline rendering and input recording/replay.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// Render frames into this directory at a fixed delta instead of playing live.
+    pub offline : Option<String>,
+    /// Number of frames to render offline.
+    pub frames  : u32,
+    /// Simulated frame rate of offline rendering.
+    pub fps     : u32,
+    /// Script input to replay instead of the player's input.
+    pub replay  : Option<String>,
+    /// File to record the player's input to (saved on exit).
+    pub record  : Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { offline: None, frames: 0, fps: DEFAULT_FPS, replay: None, record: None }
+    }
+}
+
+impl Options {
+    /// Parse the command line arguments (without the program name).
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--offline" => options.offline = Some(value()?),
+                "--frames" => options.frames = parse_count(&arg, &value()?)?,
+                "--fps" => options.fps = parse_count(&arg, &value()?)?,
+                "--replay" => options.replay = Some(value()?),
+                "--record" => options.record = Some(value()?),
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+        if options.offline.is_some() && options.frames == 0 {
+            return Err("--offline requires --frames".to_string());
+        }
+        if options.offline.is_some() && options.record.is_some() {
+            return Err("--record records live input, it cannot be combined with --offline".to_string());
+        }
+        if options.replay.is_some() && options.record.is_some() {
+            return Err("--replay and --record cannot be combined".to_string());
+        }
+        Ok(options)
+    }
+
+    /// Simulated time between two offline frames.
+    pub fn delta(&self) -> Duration {
+        Duration::from_secs(1) / self.fps
+    }
+}
+
+/// Image file of offline frame `frame` (1-based) in `dir`.
+pub fn frame_path(dir: &str, frame: u32) -> PathBuf {
+    Path::new(dir).join(format!("frame_{:06}.png", frame))
+}
+
+/// Audio file of offline rendering in `dir`.
+pub fn audio_path(dir: &str) -> PathBuf {
+    Path::new(dir).join("audio.wav")
+}
+
+fn parse_count(arg: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("{} expects a positive number, got '{}'", arg, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_offline_options() {
+        assert_eq!(parse("").unwrap(), Options::default());
+        let options = parse("--offline out --frames 120 --fps 30 --replay run.toml").unwrap();
+        assert_eq!(options.offline.as_deref(), Some("out"));
+        assert_eq!((options.frames, options.fps), (120, 30));
+        assert_eq!(options.replay.as_deref(), Some("run.toml"));
+        assert_eq!(options.delta(), Duration::from_nanos(33_333_333));
+        assert_eq!(parse("--record run.toml").unwrap().record.as_deref(), Some("run.toml"));
+        assert_eq!(frame_path("out", 7), Path::new("out/frame_000007.png"));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        assert!(parse("--offline out").is_err());
+        assert!(parse("--offline out --frames 0").is_err());
+        assert!(parse("--offline out --frames 10 --fps").is_err());
+        assert!(parse("--offline out --frames 10 --record run.toml").is_err());
+        assert!(parse("--replay a.toml --record b.toml").is_err());
+        assert!(parse("--fast").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Directory screenshots are written to (created on first use).
+pub const DIR: &str = "screenshots";
+
+/// Where the debug text of a frame is drawn, given its screenshot request
+/// (see `Game::take_screenshot_request`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Overlay {
+    /// The game systems write their debug text (into the script's debug layer,
+    /// drawn with the frame).
+    pub game_text   : bool,
+    /// The debug overlay is drawn into the captured frame.
+    pub capture     : bool,
+    /// The debug overlay is drawn onto the display (unless it is in the
+    /// captured frame copied there already).
+    pub display     : bool,
+}
+
+impl Overlay {
+    /// No request: debug text on the display only. A capture with overlay gets
+    /// it once, a clean capture gets no debug text at all.
+    pub fn for_request(request: Option<bool>) -> Overlay {
+        match request {
+            None => Overlay { game_text: true, capture: false, display: true },
+            Some(true) => Overlay { game_text: true, capture: true, display: false },
+            Some(false) => Overlay { game_text: false, capture: false, display: true },
+        }
+    }
+}
+
+/// Read back `texture` (the composed frame) and write it to a timestamped PNG
+/// in DIR. Returns the file path.
+pub fn save(texture: &Texture) -> Result<PathBuf, String> {
+    fs::create_dir_all(DIR).map_err(|e| format!("cannot create '{}': {}", DIR, e))?;
+    let path = Path::new(DIR).join(file_name(SystemTime::now()));
+    write_png(&path, texture.dimensions(), &texture.read_pixels())?;
+    Ok(path)
+}
+
+/// Write RGBA8 `pixels` (rows top to bottom) of an image of size `dimensions`
+/// to a PNG file.
+pub fn write_png(path: &Path, dimensions: (u32, u32), pixels: &[u8]) -> Result<(), String> {
+    let file = fs::File::create(path).map_err(|e| format!("cannot create '{}': {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), dimensions.0, dimensions.1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(pixels).map_err(|e| format!("cannot write '{}': {}", path.display(), e))
+}
+
+/// File name for a screenshot taken at `time` (UTC), e.g.
+/// "screenshot-2026-10-19-14-03-22-123.png".
+fn file_name(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    let time_of_day = seconds % 86400;
+    format!("screenshot-{:04}-{:02}-{:02}-{:02}-{:02}-{:02}-{:03}.png",
+        year, month, day, time_of_day / 3600, time_of_day / 60 % 60, time_of_day % 60, since_epoch.subsec_millis()
+    )
+}
+
+/// (year, month, day) of a day count since 1970-01-01 (proleptic Gregorian
+/// calendar, Howard Hinnant's days_from_civil inverse).
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = (if month_index < 10 { month_index + 3 } else { month_index - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_are_timestamped() {
+        assert_eq!(file_name(SystemTime::UNIX_EPOCH), "screenshot-1970-01-01-00-00-00-000.png");
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_792_418_602_123);
+        assert_eq!(file_name(time), "screenshot-2026-10-19-14-03-22-123.png");
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn clean_capture_has_no_debug_text() {
+        let clean = Overlay::for_request(Some(false));
+        assert!(!clean.game_text && !clean.capture);
+        // with overlay: drawn into the capture, which is copied to the display
+        let overlay = Overlay::for_request(Some(true));
+        assert!(overlay.game_text && overlay.capture && !overlay.display);
+        assert_eq!(Overlay::for_request(None), Overlay { game_text: true, capture: false, display: true });
+    }
+
+    #[test]
+    fn writes_png() {
+        let path = std::env::temp_dir().join(format!("spacegame-screenshot-{}.png", std::process::id()));
+        let pixels: Vec<u8> = (0..2 * 2 * 4).map(|i| i as u8 * 10).collect();
+        write_png(&path, (2, 2), &pixels).unwrap();
+        let decoder = png::Decoder::new(fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!((info.width, info.height), (2, 2));
+        assert_eq!(&buffer[..info.buffer_size()], &pixels[..]);
+        let _ = fs::remove_file(&path);
+    }
+}
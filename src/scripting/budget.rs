@@ -0,0 +1,88 @@
+use crate::prelude::*;
+use crate::timeframe::Timeframe;
+use super::{Api, ScriptContext};
+use std::fmt;
+use itsy::runtime::{VM, VMState};
+
+/// Number of instructions executed between two checks of the time budget.
+const TIME_CHECK_INTERVAL: u64 = 4096;
+
+/// Per-frame execution limits for the Itsy VM. A script exceeding either limit
+/// (e.g. a loop that never reaches `suspend`) is interrupted instead of freezing
+/// the game.
+#[derive(Clone, Copy, Debug)]
+pub struct ScriptBudget {
+    /// Maximum wall clock time per frame, None = no time limit (on simulated
+    /// time, where the result must not depend on the machine's speed).
+    pub max_time        : Option<Duration>,
+    /// Maximum number of VM instructions per frame.
+    pub max_instructions: u64,
+}
+
+impl Default for ScriptBudget {
+    fn default() -> Self {
+        ScriptBudget {
+            max_time        : Some(Duration::from_millis(100)),
+            max_instructions: 50_000_000,
+        }
+    }
+}
+
+/// Timing and instruction count of the last VM run (shown in the debug overlay).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScriptStats {
+    pub elapsed     : Duration,
+    pub instructions: u64,
+    /// The run was interrupted for exceeding the budget.
+    pub interrupted : bool,
+}
+
+/// Error returned by a budgeted VM run.
+#[derive(Clone, Debug)]
+pub enum ScriptError {
+    /// The VM reported a runtime error.
+    Runtime(String),
+    /// The VM exceeded the budget and was interrupted mid-frame.
+    BudgetExceeded { instructions: u64, elapsed: Duration },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Runtime(msg) => write!(f, "script runtime error: {}", msg),
+            ScriptError::BudgetExceeded { instructions, elapsed } => write!(f,
+                "script interrupted after {} instructions / {:.1} ms without reaching `suspend` (runaway loop?)",
+                instructions, Timeframe::duration_to_secs(*elapsed) * 1000.0
+            ),
+        }
+    }
+}
+
+/// Runs the VM until it suspends or terminates, like `vm.run`, but one instruction
+/// at a time so the budget can be enforced. Returns the final VM state and the
+/// stats of this run. After `ScriptError::BudgetExceeded` the VM is left
+/// mid-frame and must be reset by the caller.
+pub fn run(vm: &mut VM<Api, ScriptContext>, context: &mut ScriptContext, budget: &ScriptBudget) -> (Result<VMState, ScriptError>, ScriptStats) {
+    let start = Instant::now();
+    let mut instructions = 0u64;
+
+    let result = loop {
+        match vm.step(context) {
+            Ok(VMState::Ready) => { }
+            Ok(state) => break Ok(state),
+            Err(e) => break Err(ScriptError::Runtime(format!("{:?}", e))),
+        }
+        instructions += 1;
+        if instructions >= budget.max_instructions || (instructions % TIME_CHECK_INTERVAL == 0 && budget.max_time.is_some_and(|max_time| start.elapsed() >= max_time)) {
+            break Err(ScriptError::BudgetExceeded { instructions, elapsed: start.elapsed() });
+        }
+    };
+
+    let stats = ScriptStats {
+        elapsed     : start.elapsed(),
+        instructions: instructions,
+        interrupted : matches!(result, Err(ScriptError::BudgetExceeded { .. })),
+    };
+
+    (result, stats)
+}
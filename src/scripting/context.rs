@@ -1,7 +1,12 @@
 use crate::prelude::*;
+use crate::text;
+use crate::audio::BUS_COUNT;
+use super::TextInput;
+use serde::{Serialize, Deserialize};
+use std::path::Path;
 
 /// Snapshot of entity state passed to the Itsy script each frame.
-#[derive(Clone)]
+#[derive(Clone, Debug, Default)]
 pub struct EntityData {
     pub hitpoints : f32,
     pub position  : (f32, f32),
@@ -12,7 +17,19 @@ pub struct EntityData {
     pub faction   : u16,
 }
 
+/// Per-frame state of a named input action (see `Bindings`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionState {
+    /// Any bound input is held down.
+    pub down    : bool,
+    /// Pressed this frame, including repeat events while held.
+    pub pressed : bool,
+    /// Pressed this frame, initial press only (no repeats).
+    pub edge    : bool,
+}
+
 /// Parameters for a queued entity spawn (`ApiOp::Spawn`).
+#[derive(Clone, Debug, PartialEq)]
 pub struct SpawnRequest {
     pub entity_type : u16,
     pub sprite_id   : u32,
@@ -32,6 +49,7 @@ pub struct SpawnRequest {
     pub color_r     : f32,
     pub color_g     : f32,
     pub color_b     : f32,
+    pub depth       : f32,
     /// Game time at queue time (used to resolve Lifetime/Fading deadlines).
     pub game_time   : f32,
 }
@@ -40,10 +58,36 @@ pub struct SpawnRequest {
 /// executes them (in order) after vm.run() returns, with plain borrows of the
 /// ECS world, command buffer, caches and Infrastructure. This keeps the API
 /// functions free of raw pointers.
+#[derive(Clone, Debug, PartialEq)]
 pub enum ApiOp {
     CreateLayer { scale: f32, blend: u32 },
-    AddRenderLayer { layer_id: u32, filter: u32, component: u32 },
-    WriteText { layer_id: u32, msg: String, x: f32, y: f32, alpha: f32, menu: bool },
+    AddRenderLayer { layer_id: u32, filter_id: u32, component: u32 },
+    /// Append a filter to the chain of a render pass (index into `render_layers`).
+    AddPassFilter { pass_id: u32, filter_id: u32 },
+    /// Create a filter (ID = `filters` index + 1, assigned in order).
+    CreateFilter { kind: u32 },
+    SetFilterParam { filter_id: u32, param: u32, value: f32 },
+    /// Set the LUT (index into `lut_list`) of a color grading filter.
+    SetFilterLut { filter_id: u32, lut_id: u32 },
+    /// Compile a shader (index into `shader_list`) into a program (ID =
+    /// `programs` index + 1, assigned in order; queued for invalid shaders
+    /// too, which get no program).
+    CreateProgram { shader_id: u32 },
+    SetProgramUniform { program_id: u32, name: String, value: f32 },
+    /// Draw a layer through a program (PROGRAM_NONE = plain).
+    SetLayerProgram { layer_id: u32, program_id: u32 },
+    /// Draw an entity's sprites through a program (PROGRAM_NONE = plain).
+    SetEntityProgram { entity_id: u64, program_id: u32 },
+    /// Load a font (index into `font_list`) at `size` pixels (ID =
+    /// `fonts` index + 1, assigned in order).
+    CreateFont { font_file: u32, size: f32 },
+    /// Exposure of the tone mapping pass.
+    SetExposure(f32),
+    /// Tone mapping operator (`Api::TONE_MAPPING_*`).
+    SetToneMapping(u32),
+    /// Draw text with a font (see `text::layout`): `align` = (`Api::ALIGN_LEFT`..,
+    /// `Api::ALIGN_TOP`..), wrapped at `max_width` (0 = no wrapping).
+    WriteText { layer_id: u32, font_id: u32, msg: String, position: (f32, f32), align: (u32, u32), max_width: f32, color: (f32, f32, f32, f32) },
     SetDebugLayer(u32),
     /// Draw a background image (index into `background_list`) with a scroll
     /// offset in screen pixels; the image wraps for infinite scrolling.
@@ -53,15 +97,53 @@ pub enum ApiOp {
     ToggleFullscreen,
     /// Resize the display (applied by the main loop after swap_frame).
     SetResolution { width: u32, height: u32 },
+    /// Capture the next frame to a PNG, with or without the debug overlay.
+    TakeScreenshot { overlay: bool },
+    /// Grab (confine and recenter) or release the mouse cursor.
+    GrabCursor(bool),
+    SetCursorVisible(bool),
     RequestExit,
     RequestLevelRestart,
-    PlaySound { id: u32 },
+    PlaySound { id: u32, bus: u32 },
+    /// Load and decode a sound into the cache.
+    PreloadSound(u32),
+    /// Play a sound on the sfx bus at a position in screen pixels, or
+    /// following an entity (`entity_id` != 0, starting at its position).
+    PlaySoundAt { id: u32, x: f32, y: f32, entity_id: u64 },
+    /// Play a sound group of res/sound/groups.toml, centered or at a position
+    /// in screen pixels.
+    PlayGroup { name: String, position: Option<(f32, f32)> },
+    /// Load and decode the files of a sound group.
+    PreloadGroup(String),
+    /// Move the listener of positional sounds (None = screen center).
+    SetListener(Option<(f32, f32)>),
+    /// Play a music track (index into `music_list`), replacing the playlist.
+    PlayMusic { id: u32, crossfade: f32, looping: bool },
+    QueueMusic(u32),
+    SetMusicLoop(bool),
+    SetMusicPauseWithGame(bool),
+    StopMusic { fade: f32 },
+    SetBusVolume { bus: u32, volume: f32 },
+    SetBusMuted { bus: u32, muted: bool },
+    /// Save the player settings if they changed (`Infrastructure::save_settings`).
+    SaveSettings,
     Spawn(SpawnRequest),
     Despawn(u64),
     SetVMotion { id: u64, motion: u32, vx: f32, vy: f32 },
     SetAngle { id: u64, angle: f32 },
+    /// Draw order of an entity's sprites within their layers.
+    SetDepth { id: u64, depth: f32 },
     SetHitpoints { id: u64, hp: f32 },
     ApplyDamage { id: u64, damage: f32 },
+    /// Capture the next pressed input as the action's new binding.
+    RebindAction(String),
+    ResetBindings,
+    /// Start timer `id` (allocated by the API) at game time `game_time`.
+    StartTimer { id: u32, delay: f32, interval: f32, entity_id: u64, game_time: f32 },
+    CancelTimer(u32),
+    /// Attach `child` to `parent` (see component::Parent / LocalTransform).
+    Attach { child: u64, parent: u64, offset_x: f32, offset_y: f32, angle: f32, inherit_angle: bool, despawn_with_parent: bool },
+    Detach(u64),
 }
 
 /// Context shared between Rust and Itsy via the API.
@@ -73,6 +155,18 @@ pub struct ScriptContext {
     /// call. FIFO execution of CreateLayer ops keeps this in sync with the
     /// actual layer vector (only CreateLayer appends to it).
     pub next_layer_id: u32,
+    /// ID returned by the next add_render_layer call (index into the
+    /// render pass vector, kept in sync like `next_layer_id`).
+    pub next_pass_id: u32,
+    /// ID returned by the next create_filter call (filters vector index + 1;
+    /// 0 is FILTER_NONE).
+    pub next_filter_id: u32,
+    /// ID returned by the next create_program call (programs vector index + 1;
+    /// 0 is PROGRAM_NONE).
+    pub next_program_id: u32,
+    /// ID returned by the next start_timer call (0 is never used, so the
+    /// script can use it as "no timer").
+    pub next_timer_id: u32,
 
     /// Snapshot of entity state (rebuilt each frame).
     pub entity_data: HashMap<u64, EntityData>,
@@ -81,36 +175,65 @@ pub struct ScriptContext {
     /// Entity IDs needing scripted logic this frame.
     pub think_entities: Vec<u64>,
     pub game_time: f32,
-    /// Mouse position (set by control system). Note: may be unreliable when cursor is grabbed.
-    /// Use `mouse_delta` for relative movement.
+    /// Mouse position (set by control system). Note: unreliable while the cursor
+    /// is grabbed (see `cursor_grabbed`); use `mouse_delta` for relative movement.
     pub mouse_pos: (i32, i32),
     /// Mouse delta since last frame (set by control system). Reliable even when cursor is grabbed.
     pub mouse_delta: (i32, i32),
     /// Current display size in pixels (set by the control system each frame).
     pub screen_size: (u32, u32),
-    /// Keyboard input masks (see KEY_* constants in scripting/mod.rs).
-    /// `input_keys` = keys currently held down.
-    pub input_keys: u16,
-    /// Keys pressed this frame, including repeat events while held.
-    pub input_pressed: u16,
-    /// Keys pressed this frame, initial press only (no repeats).
-    pub input_edge: u16,
+    /// Cursor mode as last requested by the script (grab_cursor / release_cursor).
+    pub cursor_grabbed: bool,
+    /// Size of each font created by the script (`create_font`), index = font
+    /// ID - 1; estimates text sizes until the font is loaded (see `measure_text`).
+    pub font_sizes: Vec<f32>,
+    /// Fonts created by the script by ID, for text measurement (set when the
+    /// font is loaded, i.e. after the frame that created it; never in the
+    /// script harness).
+    pub fonts: HashMap<u32, Arc<Font>>,
+    /// State of the named input actions this frame (see `Bindings`); actions
+    /// missing from the map read as released.
+    pub actions: HashMap<String, ActionState>,
+    /// Human readable binding of each action (e.g. "Mouse1 / LControl").
+    pub action_bindings: HashMap<String, String>,
+    /// Action currently waiting for a new binding (rebind_action).
+    pub rebinding: Option<String>,
+    /// Active text input (begin_text_input), edited by the control system.
+    pub text_input: Option<TextInput>,
+    /// Volume and mute state per audio bus (index = `Api::BUS_*`), set by the
+    /// control system each frame and updated immediately by the setters.
+    pub bus_volumes: [f32; BUS_COUNT],
+    pub bus_muted: [bool; BUS_COUNT],
     /// Entities marked as dying this frame (for on_die dispatch).
     pub dying_entities: Vec<u64>,
+    /// Timers that fired this frame, as (timer_id, entity_id) pairs in firing order.
+    pub timer_events: Vec<(u32, u64)>,
+    /// Compile errors of programs created by the script, by program ID (set
+    /// when the program is compiled, i.e. after the frame that created it).
+    pub program_errors: HashMap<u32, String>,
     /// Random number generator for the Itsy script (seeded deterministically).
     pub rng: Rng,
     /// Sprite file paths (recursive listing of res/sprite, sorted).
     /// Generated once at startup; the vector index is the sprite ID
     /// shared between Itsy and Rust.
     pub sprite_list: Vec<String>,
-    /// Sound file paths (recursive listing of res/sound, sorted).
+    /// Sound file paths (recursive listing of res/sound, audio files only, sorted).
     /// Generated once at startup; the vector index is the sound ID
     /// shared between Itsy and Rust.
     pub sound_list: Vec<String>,
+    /// Music track file paths (recursive listing of res/music, audio files
+    /// only, sorted). The vector index is the track ID shared between Itsy and Rust.
+    pub music_list: Vec<String>,
     /// Background image file paths (recursive listing of res/background,
     /// sorted). Generated once at startup; the vector index is the background
     /// ID shared between Itsy and Rust.
     pub background_list: Vec<String>,
+    /// Color grading LUT paths (res/lut), indexed by LUT ID.
+    pub lut_list: Vec<String>,
+    /// WGSL shader paths (res/shader, .wgsl only), indexed by shader ID.
+    pub shader_list: Vec<String>,
+    /// Font file paths (res/font, .ttf and .otf only), sorted.
+    pub font_list: Vec<String>,
 }
 
 impl ScriptContext {
@@ -118,6 +241,10 @@ impl ScriptContext {
         ScriptContext {
             pending: Vec::new(),
             next_layer_id: 0,
+            next_pass_id: 0,
+            next_filter_id: 1,
+            next_program_id: 1,
+            next_timer_id: 1,
             entity_data: HashMap::new(),
             collisions: Vec::new(),
             think_entities: Vec::new(),
@@ -125,16 +252,84 @@ impl ScriptContext {
             mouse_pos: (0, 0),
             mouse_delta: (0, 0),
             screen_size: (0, 0),
-            input_keys: 0,
-            input_pressed: 0,
-            input_edge: 0,
+            cursor_grabbed: false,
+            font_sizes: Vec::new(),
+            fonts: HashMap::new(),
+            actions: HashMap::new(),
+            action_bindings: HashMap::new(),
+            rebinding: None,
+            text_input: None,
+            bus_volumes: [1.0; BUS_COUNT],
+            bus_muted: [false; BUS_COUNT],
             dying_entities: Vec::new(),
+            timer_events: Vec::new(),
+            program_errors: HashMap::new(),
             rng: Rng::new(123.4),
             sprite_list: list_files_recursive("res/sprite"),
-            sound_list: list_files_recursive("res/sound"),
+            sound_list: list_files_recursive("res/sound").into_iter().filter(|path| is_audio_file(path)).collect(),
+            music_list: list_files_recursive("res/music").into_iter().filter(|path| is_audio_file(path)).collect(),
             background_list: list_files_recursive("res/background"),
+            lut_list: list_files_recursive("res/lut"),
+            shader_list: list_files_recursive("res/shader").into_iter().filter(|path| path.ends_with(".wgsl")).collect(),
+            font_list: list_files_recursive("res/font").into_iter().filter(|path| path.ends_with(".ttf") || path.ends_with(".otf")).collect(),
         }
     }
+
+    /// Forget the state created by the running script (resource ID counters,
+    /// fonts, program errors, pending operations, text input and rebinding),
+    /// so a restarted script starts over like on its first frame. Input, the
+    /// entity snapshot and the resource lists are kept.
+    pub fn reset_script_state(&mut self) {
+        self.pending.clear();
+        self.next_layer_id = 0;
+        self.next_pass_id = 0;
+        self.next_filter_id = 1;
+        self.next_program_id = 1;
+        self.next_timer_id = 1;
+        self.font_sizes.clear();
+        self.fonts.clear();
+        self.program_errors.clear();
+        self.rebinding = None;
+        self.text_input = None;
+        self.timer_events.clear();
+    }
+
+    /// Size of `msg` in pixels as drawn by write_text with font `font_id`,
+    /// wrapped at `max_width` (0 = no wrapping). Until the font is loaded (and
+    /// in the script harness) the size is estimated from the font size: half
+    /// of it per character, one font size per line. (0, 0) for invalid fonts.
+    pub fn measure_text(&self, font_id: u32, msg: &str, max_width: f32) -> (f32, f32) {
+        let Some(&size) = self.font_sizes.get((font_id as usize).wrapping_sub(1)) else {
+            return (0.0, 0.0);
+        };
+        match self.fonts.get(&font_id) {
+            Some(font) => text::measure(msg, max_width, |line| font.measure(line)),
+            None => text::measure(msg, max_width, |line| (line.chars().count() as f32 * size * 0.5, size)),
+        }
+    }
+
+    /// Font names (file names without extension), indexed like `font_list`.
+    pub fn font_names(&self) -> Vec<String> {
+        self.font_list.iter().map(|path| Path::new(path).file_stem().unwrap_or_default().to_string_lossy().to_string()).collect()
+    }
+
+    /// Index into `font_list` of the font named `name`.
+    pub fn font_file(&self, name: &str) -> Option<usize> {
+        self.font_names().iter().position(|other| other == name)
+    }
+}
+
+impl Default for ScriptContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// True for file names with an extension rodio can decode: OGG Vorbis only,
+/// the one decoder it is built with (Cargo.toml).
+fn is_audio_file(path: &str) -> bool {
+    let extension = path.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    extension == "ogg"
 }
 
 /// Recursively lists all files under `path`, returning project-root-relative
@@ -192,4 +387,22 @@ mod tests {
         assert_eq!(backgrounds, sorted);
         assert!(backgrounds.contains(&"res/background/blue.jpg".to_string()));
     }
+
+    #[test]
+    fn lists_only_decodable_audio_files() {
+        assert!(is_audio_file("res/music/boss.ogg") && is_audio_file("res/sound/A.OGG"));
+        assert!(!is_audio_file("res/music/boss.mp3") && !is_audio_file("res/music/README.md"));
+    }
+
+    #[test]
+    fn finds_fonts_by_name_and_estimates_their_text() {
+        let mut context = ScriptContext::new();
+        let bold = context.font_file("DejaVuSans-Bold").unwrap();
+        assert_eq!(context.font_list[bold], "res/font/DejaVuSans-Bold.ttf");
+        assert_eq!(context.font_file("LICENSE"), None);
+        context.font_sizes.push(20.0);
+        assert_eq!(context.measure_text(1, "New Game", 0.0), (80.0, 20.0));
+        assert_eq!(context.measure_text(1, "New Game", 50.0), (40.0, 40.0));
+        assert_eq!(context.measure_text(2, "New Game", 0.0), (0.0, 0.0));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+use crate::prelude::*;
+use super::{Api, ScriptContext, EntityData, ActionState, ApiOp, ScriptBudget, ScriptError, TextEdit};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use itsy::runtime::{VM, VMState};
+
+/// Counter for unique driver script directories (tests run in parallel).
+static DRIVER_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Copy the Itsy scripts in `from` (and its subdirectories) to `to`.
+fn copy_scripts(from: &Path, to: &Path) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("cannot create '{}': {}", to.display(), e))?;
+    let entries = fs::read_dir(from).map_err(|e| format!("cannot read '{}': {}", from.display(), e))?;
+    for entry in entries {
+        let path: PathBuf = entry.map_err(|e| e.to_string())?.path();
+        let target = to.join(path.file_name().unwrap_or_default());
+        if path.is_dir() {
+            copy_scripts(&path, &target)?;
+        } else if path.extension().is_some_and(|extension| extension == "itsy") {
+            fs::copy(&path, &target).map_err(|e| format!("cannot copy '{}': {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Test harness for Itsy scripts: runs a script frame by frame against a scripted
+/// `ScriptContext` (fake entity snapshot, input, game time, timer events) without
+/// a window, ECS world or audio device, and returns the `ApiOp`s each frame
+/// recorded, so tests can assert on spawns, sounds, menu actions, etc.
+///
+/// A "frame" is one VM run up to the next `suspend` (or termination), exactly
+/// like `Scripting::run` does in the game; the recorded operations are returned
+/// instead of being executed.
+pub struct ScriptHarness {
+    vm      : VM<Api, ScriptContext>,
+    /// The context passed to the script. Tests set up the snapshot/input here
+    /// before calling `frame`.
+    pub context: ScriptContext,
+    budget  : ScriptBudget,
+}
+
+impl ScriptHarness {
+    /// Build the script at `path` (e.g. "res/script/game.itsy"); the root module
+    /// needs a `main` entry function.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let program = itsy::build::<Api, _>(path.as_ref()).map_err(|e| format!("compile failed:\n{}", e))?;
+        Ok(ScriptHarness {
+            vm      : VM::new(program),
+            context : ScriptContext::new(),
+            budget  : ScriptBudget::default(),
+        })
+    }
+
+    /// Build a driver script from source, e.g. to call a single Itsy function:
+    ///
+    /// `mod menu; fn main() { let s = menu::menu_show("main"); }`
+    ///
+    /// The source is compiled as if it was a file in `dir`, so its `mod`
+    /// declarations resolve relative to `dir` (e.g. "res/script"): the scripts
+    /// of `dir` are copied to a temporary directory next to the driver, `dir`
+    /// itself is not written to.
+    pub fn from_source<P: AsRef<Path>>(dir: P, source: &str) -> Result<Self, String> {
+        let id = DRIVER_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let temp_dir = std::env::temp_dir().join(format!("spacegame_harness_{}_{}", std::process::id(), id));
+        let result = copy_scripts(dir.as_ref(), &temp_dir).and_then(|_| {
+            let path = temp_dir.join("harness_driver.itsy");
+            fs::write(&path, source).map_err(|e| format!("cannot write driver script '{}': {}", path.display(), e))?;
+            Self::new(&path)
+        });
+        let _ = fs::remove_dir_all(&temp_dir);
+        result
+    }
+
+    /// Replace the per-frame execution budget (e.g. to test the watchdog).
+    pub fn set_budget(&mut self, budget: ScriptBudget) {
+        self.budget = budget;
+    }
+
+    /// Add an entity to the snapshot and to this frame's think list.
+    pub fn add_entity(&mut self, id: u64, data: EntityData) {
+        self.context.entity_data.insert(id, data);
+        if !self.context.think_entities.contains(&id) {
+            self.context.think_entities.push(id);
+        }
+    }
+
+    /// Press `action` this frame (down, pressed and edge). Stays set until
+    /// `release_all`, like holding the input would (minus the edge/repeat).
+    pub fn press(&mut self, action: &str) {
+        self.context.actions.insert(action.to_string(), ActionState { down: true, pressed: true, edge: true });
+    }
+
+    /// Hold `action` down (no press event).
+    pub fn hold(&mut self, action: &str) {
+        self.context.actions.insert(action.to_string(), ActionState { down: true, ..ActionState::default() });
+    }
+
+    /// Release all actions.
+    pub fn release_all(&mut self) {
+        self.context.actions.clear();
+    }
+
+    /// Apply an editing event to the active text input (no-op if none is active).
+    pub fn text_edit(&mut self, edit: TextEdit) {
+        if let Some(text_input) = &mut self.context.text_input {
+            text_input.apply(edit);
+        }
+    }
+
+    /// Type `text` into the active text input.
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.text_edit(TextEdit::Insert(c));
+        }
+    }
+
+    /// Advance the game time by `delta` seconds.
+    pub fn advance(&mut self, delta: f32) {
+        self.context.game_time += delta;
+    }
+
+    /// Run one frame and return the operations the script recorded. A frame
+    /// interrupted by the budget returns `ScriptError::BudgetExceeded` and
+    /// drops its operations; the script restarts at `main` next frame. Per-frame
+    /// events (timer events, collisions, dying entities, text submit/cancel) are cleared afterwards,
+    /// like the game rebuilds them each frame; the snapshot and input are kept.
+    pub fn frame(&mut self) -> Result<Vec<ApiOp>, ScriptError> {
+        let (result, _) = super::run_budgeted(&mut self.vm, &mut self.context, &self.budget);
+        let ops = std::mem::take(&mut self.context.pending);
+        self.context.timer_events.clear();
+        self.context.collisions.clear();
+        self.context.dying_entities.clear();
+        if let Some(text_input) = &mut self.context.text_input {
+            text_input.clear_events();
+        }
+        match result {
+            Ok(VMState::Error(_)) => Err(ScriptError::Runtime("VM entered error state".to_string())),
+            Ok(_) => Ok(ops),
+            Err(e) => {
+                // like the game, restart the script without its previous state
+                self.vm.reset();
+                self.context.reset_script_state();
+                Err(e)
+            }
+        }
+    }
+
+    /// Run `count` frames, advancing the game time by `delta` before each one,
+    /// and return the operations of all frames (in order).
+    pub fn frames(&mut self, count: usize, delta: f32) -> Result<Vec<ApiOp>, ScriptError> {
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            self.advance(delta);
+            ops.extend(self.frame()?);
+        }
+        Ok(ops)
+    }
+}
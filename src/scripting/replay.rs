@@ -0,0 +1,121 @@
+use crate::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use super::{ScriptContext, ActionState, TextEdit};
+
+/// Script input of one frame: what the script sees of the player's input
+/// (named actions, mouse and typed text), recorded or replayed in place of
+/// the real input, and the frame's timing.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputFrame {
+    /// Real time since the previous frame in seconds. A replay advances the
+    /// game clock by it, so the input arrives at the same game time.
+    #[serde(default)]
+    pub delta       : f64,
+    pub mouse_pos   : (i32, i32),
+    pub mouse_delta : (i32, i32),
+    /// Editing events of the active text input.
+    #[serde(default)]
+    pub text_edits  : Vec<TextEdit>,
+    /// Actions that are not released (down, pressed or edge).
+    #[serde(default)]
+    pub actions     : BTreeMap<String, ActionState>,
+}
+
+/// Recorded script input, one `InputFrame` per game frame, saved as TOML.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputReplay {
+    pub frames  : Vec<InputFrame>,
+    /// Next frame to replay.
+    #[serde(skip)]
+    position    : usize,
+}
+
+impl InputReplay {
+    pub fn load(path: &str) -> Result<InputReplay, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {}", path, e))?;
+        toml::from_str(&source).map_err(|e| format!("cannot parse '{}': {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let source = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, source).map_err(|e| format!("cannot write '{}': {}", path, e))
+    }
+
+    /// Append the input the script sees this frame, `delta` (real time) after
+    /// the previous frame.
+    pub fn record(&mut self, context: &ScriptContext, text_edits: &[TextEdit], delta: Duration) {
+        self.frames.push(InputFrame {
+            delta       : delta.as_secs_f64(),
+            mouse_pos   : context.mouse_pos,
+            mouse_delta : context.mouse_delta,
+            text_edits  : text_edits.to_vec(),
+            actions     : context.actions.iter().filter(|(_, state)| **state != ActionState::default()).map(|(action, state)| (action.clone(), *state)).collect(),
+        });
+    }
+
+    /// Apply the next recorded frame to `context` (no input once the replay
+    /// is over).
+    pub fn play(&mut self, context: &mut ScriptContext) {
+        let frame = self.frames.get(self.position).cloned().unwrap_or_default();
+        self.position += 1;
+        context.actions = frame.actions.into_iter().collect();
+        context.mouse_pos = frame.mouse_pos;
+        context.mouse_delta = frame.mouse_delta;
+        if let Some(text_input) = &mut context.text_input {
+            text_input.clear_events();
+            for edit in frame.text_edits {
+                text_input.apply(edit);
+            }
+        }
+    }
+
+    /// Recorded delta of the next frame to replay (None once the replay is over).
+    pub fn next_delta(&self) -> Option<Duration> {
+        self.frames.get(self.position).map(|frame| Duration::from_secs_f64(frame.delta.max(0.0)))
+    }
+
+    /// True once all recorded frames were played.
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.frames.len()
+    }
+}
+
+/// Where the script input comes from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum InputMode {
+    /// The player's input.
+    #[default]
+    Live,
+    /// The player's input, recorded.
+    Record(InputReplay),
+    /// A recorded input.
+    Play(InputReplay),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_recorded_input() {
+        let mut context = ScriptContext::new();
+        context.actions.insert("fire".to_string(), ActionState { down: true, pressed: true, edge: true });
+        context.actions.insert("strafe".to_string(), ActionState::default());
+        context.mouse_delta = (3, -2);
+        let mut replay = InputReplay::default();
+        replay.record(&context, &[ TextEdit::Insert('a'), TextEdit::Submit ], Duration::from_micros(15_625));
+        assert_eq!(replay.frames[0].actions.len(), 1);
+
+        let mut replay: InputReplay = toml::from_str(&toml::to_string(&replay).unwrap()).unwrap();
+        let mut context = ScriptContext::new();
+        assert_eq!(replay.next_delta(), Some(Duration::from_micros(15_625)));
+        replay.play(&mut context);
+        assert_eq!(replay.next_delta(), None);
+        assert!(context.actions["fire"].edge);
+        assert_eq!(context.mouse_delta, (3, -2));
+        assert!(replay.is_finished());
+        replay.play(&mut context);
+        assert!(context.actions.is_empty());
+    }
+}
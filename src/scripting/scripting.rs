@@ -1,33 +1,32 @@
 mod context;
+mod budget;
+mod harness;
+mod text_input;
+mod replay;
 
-pub use self::context::{ScriptContext, EntityData, ApiOp, SpawnRequest};
+pub use self::context::{ScriptContext, EntityData, ActionState, ApiOp, SpawnRequest};
+pub use self::budget::{ScriptBudget, ScriptStats, ScriptError};
+pub use self::budget::run as run_budgeted;
+pub use self::harness::ScriptHarness;
+pub use self::text_input::{TextInput, TextEdit};
+pub use self::replay::{InputReplay, InputFrame, InputMode};
 
 use crate::prelude::*;
+use crate::audio::BUS_COUNT;
 use itsy;
 
 // Define the Itsy API type.
 //
-// The Rust<->Itsy protocol constants (key bits, entity types, spawn triggers,
+// The Rust<->Itsy protocol constants (entity types, spawn triggers,
 // motion/blend/filter encodings and the "no layer" sentinel) are declared once,
 // before any function, and become associated constants on `Api`. Rust code
-// refers to them as `Api::KEY_W`; the Itsy script refers to the same definition
-// (as `Api::KEY_W`, or bare `KEY_W` after `use Api::KEY_W`). This makes the
+// refers to them as `Api::ET_PLAYER`; the Itsy script refers to the same definition
+// (as `Api::ET_PLAYER`, or bare `ET_PLAYER` after `use Api::ET_PLAYER`). This makes the
 // macro the single source of truth, so the two sides can never drift.
+// Input is not part of the protocol: the script queries named actions
+// (res/config/bindings.toml) via action_down / action_pressed / action_edge.
 itsy::itsy_api! {
     pub Api<ScriptContext> {
-        // Input key bits (the masks passed to Itsy each frame via set_input_state).
-        const KEY_W           : u16 = 1;
-        const KEY_S           : u16 = 2;
-        const KEY_A           : u16 = 4;
-        const KEY_D           : u16 = 8;
-        const KEY_RSHIFT      : u16 = 16;
-        const KEY_CURSOR_UP   : u16 = 32;
-        const KEY_CURSOR_DOWN : u16 = 64;
-        const KEY_RETURN      : u16 = 128;
-        const KEY_ESCAPE      : u16 = 256;
-        const KEY_MOUSE1      : u16 = 512;
-        const KEY_LCONTROL    : u16 = 1024;
-
         // Entity type IDs.
         const ET_NONE         : u16 = 0;
         const ET_PLAYER       : u16 = 1;
@@ -54,10 +53,56 @@ itsy::itsy_api! {
         const BLEND_ADD     : u32 = 1;
         const BLEND_LIGHTEN : u32 = 2;
 
-        // Render pass filters (add_render_layer).
+        // Post-processing filter kinds (create_filter). FILTER_NONE is also the
+        // "no filter" ID for add_render_layer.
         const FILTER_NONE  : u32 = 0;
         const FILTER_BLOOM : u32 = 1;
         const FILTER_GLARE : u32 = 2;
+        const FILTER_VIGNETTE      : u32 = 3;
+        const FILTER_CRT           : u32 = 4;
+        const FILTER_CHROMATIC     : u32 = 5;
+        const FILTER_COLOR_GRADING : u32 = 6;  // LUT from get_luts (set_filter_lut)
+
+        // Filter parameters (set_filter_param); order matches FilterParam.
+        const FILTER_PARAM_ITERATIONS : u32 = 0;  // blur passes
+        const FILTER_PARAM_SPREAD     : u32 = 1;  // blurred levels, 1..=8, limited by the resolution (glare only)
+        const FILTER_PARAM_BRIGHTNESS : u32 = 2;  // bloom: overlay alpha, glare: brightness
+        const FILTER_PARAM_FADE       : u32 = 3;  // alpha of the fade fill (lower = longer trails)
+        const FILTER_PARAM_STRENGTH   : u32 = 4;  // vignette darkness, color grading amount (0..=1)
+        const FILTER_PARAM_RADIUS     : u32 = 5;  // vignette: undarkened radius (1 = corners)
+        const FILTER_PARAM_SOFTNESS   : u32 = 6;  // vignette: width of the darkening edge
+        const FILTER_PARAM_CURVATURE  : u32 = 7;  // crt: screen curvature
+        const FILTER_PARAM_SCANLINES  : u32 = 8;  // crt: scanline darkness (0..=1)
+        const FILTER_PARAM_OFFSET     : u32 = 9;  // chromatic: channel shift at the edges in pixels
+        const FILTER_PARAM_THRESHOLD  : u32 = 10; // glare: brightness below which pixels do not glow (0 = all)
+        const FILTER_PARAM_KNEE       : u32 = 11; // glare: width of the soft transition around the threshold
+        const FILTER_PARAM_KERNEL     : u32 = 12; // glare: blur kernel radius in taps, 1..=8
+        const FILTER_PARAM_CLAMP      : u32 = 13; // glare: maximum output per color channel
+
+        // Tone mapping operators (set_tone_mapping); order matches effects::ToneMapping.
+        const TONE_MAPPING_NONE     : u32 = 0;  // clamp to 1
+        const TONE_MAPPING_REINHARD : u32 = 1;
+        const TONE_MAPPING_ACES     : u32 = 2;
+
+        // "No program" ID for set_layer_program / set_entity_program.
+        const PROGRAM_NONE : u32 = 0;
+
+        // "No font" ID (create_font failed).
+        const FONT_NONE : u32 = 0;
+
+        // Text alignment (write_text); order matches text::HAlign / text::VAlign.
+        const ALIGN_LEFT   : u32 = 0;
+        const ALIGN_CENTER : u32 = 1;
+        const ALIGN_RIGHT  : u32 = 2;
+        const ALIGN_TOP    : u32 = 0;
+        const ALIGN_MIDDLE : u32 = 1;
+        const ALIGN_BOTTOM : u32 = 2;
+
+        // Audio buses (play_sound_on_bus, set_bus_volume); order matches audio::Bus.
+        const BUS_MASTER : u32 = 0;
+        const BUS_SFX    : u32 = 1;
+        const BUS_MUSIC  : u32 = 2;
+        const BUS_UI     : u32 = 3;
 
         // "No layer" sentinel for layer / effect_layer IDs (Rust treats it as absent).
         const LAYER_ID_NONE : u32 = u32::MAX;
@@ -116,18 +161,77 @@ itsy::itsy_api! {
         fn get_mouse_delta_y(&mut context) -> f32 {
             context.mouse_delta.1 as f32 // TODO why f32
         }
-        /// Keys currently held down (level-triggered `down` semantics).
-        fn get_input_keys(&mut context) -> u16 {
-            context.input_keys
+        /// True while any input bound to the named action is held down
+        /// (level-triggered `down` semantics). Unknown actions are never down.
+        fn action_down(&mut context, action: String) -> bool {
+            context.actions.get(&action).map(|a| a.down).unwrap_or(false)
+        }
+        /// True if the action was pressed this frame, including repeat events
+        /// while held (edge-triggered `pressed(key, true)` semantics).
+        fn action_pressed(&mut context, action: String) -> bool {
+            context.actions.get(&action).map(|a| a.pressed).unwrap_or(false)
+        }
+        /// True if the action was pressed this frame, initial press only (no repeats).
+        fn action_edge(&mut context, action: String) -> bool {
+            context.actions.get(&action).map(|a| a.edge).unwrap_or(false)
+        }
+        /// Human readable inputs bound to an action, e.g. "Mouse1 / LControl"
+        /// ("-" if unbound or unknown).
+        fn get_action_binding(&mut context, action: String) -> String {
+            context.action_bindings.get(&action).cloned().unwrap_or_else(|| "-".to_string())
+        }
+        /// Rebind an action: the next key or mouse button pressed becomes its
+        /// only binding (Escape cancels) and the bindings are saved to
+        /// user/bindings.toml. All actions read as released while waiting.
+        fn rebind_action(&mut context, action: String) {
+            if !context.action_bindings.contains_key(&action) {
+                eprintln!("rebind_action: unknown action '{}'", action);
+                return;
+            }
+            context.pending.push(ApiOp::RebindAction(action));
+        }
+        /// True while an action is waiting for its new binding (see rebind_action).
+        fn is_rebinding(&mut context) -> bool {
+            context.rebinding.is_some()
+        }
+        /// Restore the default bindings (res/config/bindings.toml) and save them.
+        fn reset_bindings(&mut context) {
+            context.pending.push(ApiOp::ResetBindings);
+        }
+        /// Start a text input (e.g. a name entry) with initial `text`, limited to
+        /// `max_len` characters (0 = unlimited). Typed characters and editing keys
+        /// (Backspace, Delete, cursor, Home, End) edit the text until
+        /// end_text_input; all actions read as released meanwhile.
+        fn begin_text_input(&mut context, text: String, max_len: u32) {
+            context.text_input = Some(TextInput::new(&text, max_len as usize));
+        }
+        /// End the text input (after reading the submitted text, or on cancel).
+        fn end_text_input(&mut context) {
+            context.text_input = None;
+        }
+        /// True while a text input is active.
+        fn is_text_input_active(&mut context) -> bool {
+            context.text_input.is_some()
         }
-        /// Keys pressed this frame, including repeat events while held
-        /// (edge-triggered `pressed(key, true)` semantics).
-        fn get_input_pressed(&mut context) -> u16 {
-            context.input_pressed
+        /// The text entered so far ("" if no text input is active).
+        fn get_text_input(&mut context) -> String {
+            context.text_input.as_ref().map(|t| t.text()).unwrap_or_default()
         }
-        /// Keys pressed this frame, initial press only (no repeats).
-        fn get_input_edge(&mut context) -> u16 {
-            context.input_edge
+        /// The text before the caret (to measure the caret position).
+        fn get_text_before_caret(&mut context) -> String {
+            context.text_input.as_ref().map(|t| t.head()).unwrap_or_default()
+        }
+        /// Caret position in characters.
+        fn get_text_caret(&mut context) -> u32 {
+            context.text_input.as_ref().map(|t| t.caret() as u32).unwrap_or(0)
+        }
+        /// True if Return was pressed this frame (the text is complete).
+        fn text_input_submitted(&mut context) -> bool {
+            context.text_input.as_ref().map(|t| t.submitted()).unwrap_or(false)
+        }
+        /// True if Escape was pressed this frame (discard the text).
+        fn text_input_cancelled(&mut context) -> bool {
+            context.text_input.as_ref().map(|t| t.cancelled()).unwrap_or(false)
         }
         fn get_dying_count(&mut context) -> i32 {
             context.dying_entities.len() as i32
@@ -135,6 +239,18 @@ itsy::itsy_api! {
         fn get_dying_id(&mut context, index: u32) -> u64 {
             context.dying_entities.get(index as usize).copied().unwrap_or(0)
         }
+        /// Number of timers that fired this frame (see `start_timer`).
+        fn get_timer_event_count(&mut context) -> i32 {
+            context.timer_events.len() as i32
+        }
+        /// ID of the `index`-th timer that fired this frame (firing order).
+        fn get_timer_event_id(&mut context, index: u32) -> u32 {
+            context.timer_events.get(index as usize).map(|e| e.0).unwrap_or(0)
+        }
+        /// Entity the `index`-th fired timer is bound to (0 = unbound).
+        fn get_timer_event_entity(&mut context, index: u32) -> u64 {
+            context.timer_events.get(index as usize).map(|e| e.1).unwrap_or(0)
+        }
         fn get_rand_range(&mut context, min: f32, max: f32) -> f32 {
             context.rng.range(min, max)
         }
@@ -144,13 +260,20 @@ itsy::itsy_api! {
         fn get_sprites(&mut context) -> [ String ] {
             context.sprite_list.clone()
         }
-        /// All sound file paths (recursive listing of res/sound, sorted).
+        /// All sound file paths (recursive listing of res/sound, OGG Vorbis
+        /// files only, sorted).
         /// Generated once on the Rust side; the returned vector index is the
         /// sound ID shared between Itsy and Rust.
         /// The script groups files into sound effects itself.
         fn get_sounds(&mut context) -> [ String ] {
             context.sound_list.clone()
         }
+        /// All music track file paths (recursive listing of res/music, OGG
+        /// Vorbis files only, sorted). The returned vector index is the track ID
+        /// shared between Itsy and Rust.
+        fn get_music(&mut context) -> [ String ] {
+            context.music_list.clone()
+        }
         /// All background image file paths (recursive listing of res/background,
         /// sorted). Generated once on the Rust side; the returned vector index
         /// is the background ID shared between Itsy and Rust.
@@ -170,24 +293,123 @@ itsy::itsy_api! {
             id
         }
         /// Register a render pass for a layer (in draw order), mirroring the old
-        /// layer.yaml "render" section.
-        /// `filter`: 0 = none, 1 = bloom, 2 = glare. `component` = z-order
+        /// layer.yaml "render" section. Returns the pass ID.
+        /// `filter_id`: a filter from create_filter, or FILTER_NONE; more
+        /// filters can be chained with add_pass_filter. `component` = z-order
         /// within the layer.
-        fn add_render_layer(&mut context, layer_id: u32, filter: u32, component: u32) {
-            context.pending.push(ApiOp::AddRenderLayer { layer_id, filter, component });
+        fn add_render_layer(&mut context, layer_id: u32, filter_id: u32, component: u32) -> u32 {
+            let id = context.next_pass_id;
+            context.next_pass_id += 1;
+            context.pending.push(ApiOp::AddRenderLayer { layer_id, filter_id, component });
+            id
+        }
+        /// Append a filter to a render pass's chain: it processes the output of
+        /// the filters before it. A filter is in a chain at most once (adding
+        /// it again is ignored); create another instance to apply it twice.
+        fn add_pass_filter(&mut context, pass_id: u32, filter_id: u32) {
+            context.pending.push(ApiOp::AddPassFilter { pass_id, filter_id });
+        }
+        /// Create a post-processing filter instance of a kind (FILTER_BLOOM,
+        /// FILTER_GLARE, FILTER_VIGNETTE, ...) with default parameters. Returns
+        /// its ID (never FILTER_NONE).
+        fn create_filter(&mut context, kind: u32) -> u32 {
+            let id = context.next_filter_id;
+            context.next_filter_id += 1;
+            context.pending.push(ApiOp::CreateFilter { kind });
+            id
+        }
+        /// Set a filter parameter (FILTER_PARAM_*); can change every frame,
+        /// e.g. to pulse the bloom.
+        fn set_filter_param(&mut context, filter_id: u32, param: u32, value: f32) {
+            context.pending.push(ApiOp::SetFilterParam { filter_id, param, value });
+        }
+        /// All color grading LUT file paths (res/lut, sorted); the index is the LUT ID.
+        fn get_luts(&mut context) -> [ String ] {
+            context.lut_list.clone()
+        }
+        /// Grade a FILTER_COLOR_GRADING filter with a LUT (index into get_luts()).
+        fn set_filter_lut(&mut context, filter_id: u32, lut_id: u32) {
+            if (lut_id as usize) >= context.lut_list.len() {
+                eprintln!("set_filter_lut: invalid id {}", lut_id);
+                return;
+            }
+            context.pending.push(ApiOp::SetFilterLut { filter_id, lut_id });
+        }
+        /// Set the exposure the HDR frame is multiplied by before tone mapping
+        /// (1 = unchanged).
+        fn set_exposure(&mut context, exposure: f32) {
+            context.pending.push(ApiOp::SetExposure(exposure));
+        }
+        /// Select the tone mapping operator (TONE_MAPPING_*) mapping the HDR
+        /// frame to the display.
+        fn set_tone_mapping(&mut context, mapping: u32) {
+            context.pending.push(ApiOp::SetToneMapping(mapping));
+        }
+        /// All WGSL shader file paths (res/shader, sorted); the index is the shader ID.
+        fn get_shaders(&mut context) -> [ String ] {
+            context.shader_list.clone()
+        }
+        /// Compile a shader (index into get_shaders()) into a program and return
+        /// its ID (never PROGRAM_NONE). Shaders are texture fragment shaders as
+        /// used by the screen effects (see res/shader/shimmer.wgsl). Programs
+        /// are compiled after the frame, so a compile error can be queried with
+        /// get_program_error from the next frame on.
+        fn create_program(&mut context, shader_id: u32) -> u32 {
+            let id = context.next_program_id;
+            context.next_program_id += 1;
+            // always queued (also for an invalid shader), so the IDs assigned
+            // here and by the Rust side stay in sync
+            if (shader_id as usize) >= context.shader_list.len() {
+                context.program_errors.insert(id, format!("invalid shader id {}", shader_id));
+            }
+            context.pending.push(ApiOp::CreateProgram { shader_id });
+            id
         }
-        /// Draw text in white (alpha 0..=1) on a layer.
-        fn write_text(&mut context, layer_id: u32, msg: String, x: f32, y: f32, alpha: f32) {
-            context.pending.push(ApiOp::WriteText { layer_id, msg, x, y, alpha, menu: false });
+        /// Compile error of a program ("" if it compiled or is not compiled yet).
+        fn get_program_error(&mut context, program_id: u32) -> String {
+            context.program_errors.get(&program_id).cloned().unwrap_or_default()
+        }
+        /// Set a float uniform of a program. Uniforms land in
+        /// texture_uniforms._rd_flags.x, .y, ... in the order they are first set.
+        fn set_program_uniform(&mut context, program_id: u32, name: String, value: f32) {
+            context.pending.push(ApiOp::SetProgramUniform { program_id, name, value });
+        }
+        /// Draw a layer through a program in all its render passes (before
+        /// their filters); PROGRAM_NONE draws it plain again.
+        fn set_layer_program(&mut context, layer_id: u32, program_id: u32) {
+            context.pending.push(ApiOp::SetLayerProgram { layer_id, program_id });
+        }
+        /// Draw an entity's sprites through a program (PROGRAM_NONE = plain).
+        /// They are drawn after the rest of their layer.
+        fn set_entity_program(&mut context, entity_id: u64, program_id: u32) {
+            context.pending.push(ApiOp::SetEntityProgram { entity_id, program_id });
+        }
+        /// All font names (file names in res/font without extension, sorted).
+        fn get_fonts(&mut context) -> [ String ] {
+            context.font_names()
+        }
+        /// Load a font by name (see get_fonts) at `size` pixels and return its
+        /// ID, or FONT_NONE if there is no such font.
+        fn create_font(&mut context, name: String, size: f32) -> u32 {
+            let Some(font_file) = context.font_file(&name) else {
+                eprintln!("create_font: unknown font '{}'", name);
+                return Api::FONT_NONE;
+            };
+            context.font_sizes.push(size);
+            context.pending.push(ApiOp::CreateFont { font_file: font_file as u32, size });
+            context.font_sizes.len() as u32
+        }
+        /// Draw text on a layer with a font, in a color (alpha 0..=1). The text
+        /// is aligned to (x, y) by `align_x` (ALIGN_LEFT / CENTER / RIGHT) and
+        /// `align_y` (ALIGN_TOP / MIDDLE / BOTTOM), and wrapped between words
+        /// at `max_width` pixels (0 = only at line breaks).
+        fn write_text(&mut context, layer_id: u32, font_id: u32, msg: String, x: f32, y: f32, align_x: u32, align_y: u32, max_width: f32, color_r: f32, color_g: f32, color_b: f32, alpha: f32) {
+            context.pending.push(ApiOp::WriteText { layer_id, font_id, msg, position: (x, y), align: (align_x, align_y), max_width, color: (color_r, color_g, color_b, alpha) });
         }
         /// Tell Rust which layer to use for its own debug text output.
         fn set_debug_layer(&mut context, layer_id: u32) {
             context.pending.push(ApiOp::SetDebugLayer(layer_id));
         }
-        /// Draw menu text (80 px bold font) in white (alpha 0..=1) on a layer.
-        fn write_menu_text(&mut context, layer_id: u32, msg: String, x: f32, y: f32, alpha: f32) {
-            context.pending.push(ApiOp::WriteText { layer_id, msg, x, y, alpha, menu: true });
-        }
         /// Lerp the game time rate to 0 over 500 ms (pause, e.g. when the menu opens).
         fn pause_time(&mut context) {
             context.pending.push(ApiOp::PauseTime);
@@ -208,6 +430,11 @@ itsy::itsy_api! {
         fn toggle_fullscreen(&mut context) {
             context.pending.push(ApiOp::ToggleFullscreen);
         }
+        /// Capture the next frame as composed on screen (after post-processing)
+        /// to a timestamped PNG in screenshots/, with or without the debug overlay.
+        fn take_screenshot(&mut context, overlay: bool) {
+            context.pending.push(ApiOp::TakeScreenshot { overlay });
+        }
         /// Change the display resolution. The window is resized live and the
         /// game keeps running (all game state survives). If the game is in
         /// fullscreen, it switches to windowed first (the window is locked to
@@ -220,6 +447,35 @@ itsy::itsy_api! {
             eprintln!("[debug] set_resolution({width}, {height}) queued");
             context.pending.push(ApiOp::SetResolution { width, height });
         }
+        /// Confine the mouse cursor to the window (recentered each frame, so
+        /// only get_mouse_delta_* is meaningful), e.g. while playing.
+        fn grab_cursor(&mut context) {
+            context.cursor_grabbed = true;
+            context.pending.push(ApiOp::GrabCursor(true));
+        }
+        /// Release the mouse cursor, e.g. while a menu is open; get_mouse_x/y
+        /// then report the pointer position in window pixels.
+        fn release_cursor(&mut context) {
+            context.cursor_grabbed = false;
+            context.pending.push(ApiOp::GrabCursor(false));
+        }
+        /// True while the cursor is grabbed (see grab_cursor).
+        fn is_cursor_grabbed(&mut context) -> bool {
+            context.cursor_grabbed
+        }
+        /// Show or hide the mouse cursor (independent of grabbing).
+        fn set_cursor_visible(&mut context, visible: bool) {
+            context.pending.push(ApiOp::SetCursorVisible(visible));
+        }
+        /// Width in pixels of `msg` drawn with write_text with a font, wrapped
+        /// at `max_width` (e.g. for menu item hit boxes).
+        fn measure_text_width(&mut context, font_id: u32, msg: String, max_width: f32) -> f32 {
+            context.measure_text(font_id, &msg, max_width).0
+        }
+        /// Height in pixels of `msg` drawn with write_text with a font, wrapped at `max_width`.
+        fn measure_text_height(&mut context, font_id: u32, msg: String, max_width: f32) -> f32 {
+            context.measure_text(font_id, &msg, max_width).1
+        }
         /// Current display width in pixels.
         fn get_screen_width(&mut context) -> f32 {
             context.screen_size.0 as f32 // TODO why f32
@@ -228,14 +484,138 @@ itsy::itsy_api! {
         fn get_screen_height(&mut context) -> f32 {
             context.screen_size.1 as f32 // TODO why f32
         }
-        /// Play a sound file by ID (index into `get_sounds()`).
+        /// Play a sound file by ID (index into `get_sounds()`) on the sfx bus.
         /// Files are loaded on first use and cached.
         fn play_sound(&mut context, id: u32) {
             if (id as usize) >= context.sound_list.len() {
                 eprintln!("play_sound: invalid id {}", id);
                 return;
             }
-            context.pending.push(ApiOp::PlaySound { id });
+            context.pending.push(ApiOp::PlaySound { id, bus: Api::BUS_SFX });
+        }
+        /// Load and decode a sound file ahead of its first play (sounds are
+        /// decoded once and cached either way).
+        fn preload_sound(&mut context, id: u32) {
+            if (id as usize) >= context.sound_list.len() {
+                eprintln!("preload_sound: invalid id {}", id);
+                return;
+            }
+            context.pending.push(ApiOp::PreloadSound(id));
+        }
+        /// Play a sound file by ID on the sfx bus at screen position (x, y): it
+        /// is panned by its horizontal offset from the listener (screen center,
+        /// see set_listener) and attenuated with distance.
+        fn play_sound_at(&mut context, id: u32, x: f32, y: f32) {
+            if (id as usize) >= context.sound_list.len() {
+                eprintln!("play_sound_at: invalid id {}", id);
+                return;
+            }
+            context.pending.push(ApiOp::PlaySoundAt { id, x, y, entity_id: 0 });
+        }
+        /// Play a sound file by ID at an entity's position, following the entity
+        /// while the sound plays (it stays where the entity died).
+        fn play_sound_at_entity(&mut context, id: u32, entity_id: u64) {
+            if (id as usize) >= context.sound_list.len() {
+                eprintln!("play_sound_at_entity: invalid id {}", id);
+                return;
+            }
+            let (x, y) = context.entity_data.get(&entity_id).map(|e| e.position).unwrap_or((0.0, 0.0));
+            context.pending.push(ApiOp::PlaySoundAt { id, x, y, entity_id });
+        }
+        /// Play a sound group of res/sound/groups.toml: one of its variants
+        /// (picked by weight) with randomized volume and pitch, on its bus.
+        fn play_group(&mut context, name: String) {
+            context.pending.push(ApiOp::PlayGroup { name, position: None });
+        }
+        /// Play a sound group at screen position (x, y), like play_sound_at.
+        fn play_group_at(&mut context, name: String, x: f32, y: f32) {
+            context.pending.push(ApiOp::PlayGroup { name, position: Some((x, y)) });
+        }
+        /// Load and decode all variants of a sound group ahead of its first play.
+        fn preload_group(&mut context, name: String) {
+            context.pending.push(ApiOp::PreloadGroup(name));
+        }
+        /// Hear positional sounds from (x, y) in screen pixels, e.g. a camera position.
+        fn set_listener(&mut context, x: f32, y: f32) {
+            context.pending.push(ApiOp::SetListener(Some((x, y))));
+        }
+        /// Hear positional sounds from the screen center again (default).
+        fn reset_listener(&mut context) {
+            context.pending.push(ApiOp::SetListener(None));
+        }
+        /// Play a sound file by ID on a bus (`BUS_*`, e.g. BUS_UI for menu sounds).
+        fn play_sound_on_bus(&mut context, id: u32, bus: u32) {
+            if (id as usize) >= context.sound_list.len() || bus as usize >= BUS_COUNT {
+                eprintln!("play_sound_on_bus: invalid id {} or bus {}", id, bus);
+                return;
+            }
+            context.pending.push(ApiOp::PlaySound { id, bus });
+        }
+        /// Play a music track (index into `get_music()`) now, crossfading from
+        /// the current track over `crossfade` seconds. Replaces the playlist;
+        /// the track repeats if `looping`. Tracks are streamed from disk.
+        fn play_music(&mut context, id: u32, crossfade: f32, looping: bool) {
+            if (id as usize) >= context.music_list.len() {
+                eprintln!("play_music: invalid id {}", id);
+                return;
+            }
+            context.pending.push(ApiOp::PlayMusic { id, crossfade, looping });
+        }
+        /// Append a track to the playlist (played after the current one, or
+        /// right away if no music is playing).
+        fn queue_music(&mut context, id: u32) {
+            if (id as usize) >= context.music_list.len() {
+                eprintln!("queue_music: invalid id {}", id);
+                return;
+            }
+            context.pending.push(ApiOp::QueueMusic(id));
+        }
+        /// Repeat the playlist when it ends.
+        fn set_music_loop(&mut context, looping: bool) {
+            context.pending.push(ApiOp::SetMusicLoop(looping));
+        }
+        /// Whether music started from now on fades down and pauses with the game
+        /// time (default: true); playing and fading tracks keep theirs. Disable
+        /// for music that should keep playing while paused, e.g. in the menu.
+        fn set_music_pause_with_game(&mut context, pause_with_game: bool) {
+            context.pending.push(ApiOp::SetMusicPauseWithGame(pause_with_game));
+        }
+        /// Fade out the music over `fade` seconds and clear the playlist.
+        fn stop_music(&mut context, fade: f32) {
+            context.pending.push(ApiOp::StopMusic { fade });
+        }
+        /// Set the volume (0..=1) of a bus (`BUS_*`). Applies to playing sounds
+        /// too; saved to user/settings.toml by `save_settings` (and when the game
+        /// ends). BUS_MASTER scales all buses.
+        fn set_bus_volume(&mut context, bus: u32, volume: f32) {
+            if bus as usize >= BUS_COUNT {
+                eprintln!("set_bus_volume: invalid bus {}", bus);
+                return;
+            }
+            let volume = volume.clamp(0.0, 1.0);
+            context.bus_volumes[bus as usize] = volume;
+            context.pending.push(ApiOp::SetBusVolume { bus, volume });
+        }
+        /// Volume (0..=1) of a bus (`BUS_*`), regardless of muting.
+        fn get_bus_volume(&mut context, bus: u32) -> f32 {
+            context.bus_volumes.get(bus as usize).copied().unwrap_or(0.0)
+        }
+        /// Mute or unmute a bus (`BUS_*`), keeping its volume; saved like the volume.
+        fn set_bus_muted(&mut context, bus: u32, muted: bool) {
+            if bus as usize >= BUS_COUNT {
+                eprintln!("set_bus_muted: invalid bus {}", bus);
+                return;
+            }
+            context.bus_muted[bus as usize] = muted;
+            context.pending.push(ApiOp::SetBusMuted { bus, muted });
+        }
+        fn is_bus_muted(&mut context, bus: u32) -> bool {
+            context.bus_muted.get(bus as usize).copied().unwrap_or(false)
+        }
+        /// Save the bus volumes and mute states changed since the last save to
+        /// user/settings.toml, e.g. when the audio menu closes.
+        fn save_settings(&mut context) {
+            context.pending.push(ApiOp::SaveSettings);
         }
         /// Show a background image (index into `get_backgrounds()`) this frame,
         /// scrolling it by `offset_x` / `offset_y` screen pixels.
@@ -252,6 +632,21 @@ itsy::itsy_api! {
             }
             context.pending.push(ApiOp::DrawBackground { id, offset_x, offset_y });
         }
+        /// Start a timer on game time and return its ID (never 0). It first fires
+        /// `delay` seconds from now, then every `interval` seconds (0 = one-shot).
+        /// If `entity_id` is not 0, the timer is bound to that entity and cancelled
+        /// when it dies. Firings are reported each frame via get_timer_event_*;
+        /// timers follow pause and time rate like all game time.
+        fn start_timer(&mut context, delay: f32, interval: f32, entity_id: u64) -> u32 {
+            let id = context.next_timer_id;
+            context.next_timer_id += 1;
+            context.pending.push(ApiOp::StartTimer { id, delay, interval, entity_id, game_time: context.game_time });
+            id
+        }
+        /// Cancel a timer (no-op if it already fired or was cancelled).
+        fn cancel_timer(&mut context, timer_id: u32) {
+            context.pending.push(ApiOp::CancelTimer(timer_id));
+        }
         fn debug_print(&mut _context, msg: String) {
             eprintln!("ITSY: {}", msg);
         }
@@ -269,12 +664,14 @@ itsy::itsy_api! {
         /// `get_layers()`; `u32::MAX` as a layer ID means "no layer".
         /// `color_r/g/b` tint the sprite (alpha is always 1.0; values may exceed 1.0
         /// on additive layers).
-        fn spawn_entity(&mut context, entity_type: u16, sprite_id: u32, layer_id: u32, effect_layer_id: u32, px: f32, py: f32, angle: f32, vx: f32, vy: f32, faction: u16, hitpoints: f32, radius: f32, lifetime: f32, fade: f32, fps: u32, color_r: f32, color_g: f32, color_b: f32) {
+        /// `depth` orders the sprites within each layer: higher depths are drawn
+        /// on top, equal depths in no particular but stable order.
+        fn spawn_entity(&mut context, entity_type: u16, sprite_id: u32, layer_id: u32, effect_layer_id: u32, px: f32, py: f32, angle: f32, vx: f32, vy: f32, faction: u16, hitpoints: f32, radius: f32, lifetime: f32, fade: f32, fps: u32, color_r: f32, color_g: f32, color_b: f32, depth: f32) {
             context.pending.push(ApiOp::Spawn(SpawnRequest {
                 entity_type, sprite_id, layer_id, effect_layer_id,
                 px, py, angle, vx, vy, faction,
                 hitpoints, radius, lifetime, fade, fps,
-                color_r, color_g, color_b,
+                color_r, color_g, color_b, depth,
                 game_time: context.game_time,
             }));
         }
@@ -290,11 +687,34 @@ itsy::itsy_api! {
         fn set_angle(&mut context, entity_id: u64, angle: f32) {
             context.pending.push(ApiOp::SetAngle { id: entity_id, angle });
         }
+        /// Change the draw order of an entity's sprites (see spawn_entity).
+        fn set_depth(&mut context, entity_id: u64, depth: f32) {
+            context.pending.push(ApiOp::SetDepth { id: entity_id, depth });
+        }
         fn set_hitpoints(&mut context, entity_id: u64, hp: f32) {
             context.pending.push(ApiOp::SetHitpoints { id: entity_id, hp });
         }
         fn apply_damage(&mut context, entity_id: u64, damage: f32) {
             context.pending.push(ApiOp::ApplyDamage { id: entity_id, damage });
         }
+        /// Attach an entity to a parent entity. The child's position and angle are
+        /// then derived from the parent each frame: `offset_x` / `offset_y` is the
+        /// offset from the parent's position and `angle` the child's angle, both
+        /// relative to the parent's angle if `inherit_angle` is set (e.g. a turret),
+        /// absolute otherwise (e.g. a shield bubble). When the parent dies, the
+        /// child dies with it if `despawn_with_parent` is set (reported to `on_die`
+        /// like the parent), detached otherwise. Attaching an entity to itself or
+        /// to one of its own descendants is refused.
+        fn attach_entity(&mut context, child_id: u64, parent_id: u64, offset_x: f32, offset_y: f32, angle: f32, inherit_angle: bool, despawn_with_parent: bool) {
+            if child_id == parent_id {
+                eprintln!("attach_entity: cannot attach entity {} to itself", child_id);
+                return;
+            }
+            context.pending.push(ApiOp::Attach { child: child_id, parent: parent_id, offset_x, offset_y, angle, inherit_angle, despawn_with_parent });
+        }
+        /// Detach an entity from its parent; it keeps its current position and angle.
+        fn detach_entity(&mut context, child_id: u64) {
+            context.pending.push(ApiOp::Detach(child_id));
+        }
     }
 }
@@ -0,0 +1,203 @@
+use serde::{Serialize, Deserialize};
+
+/// Editing event of the text input buffer, collected from the typed text and editing keys each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TextEdit {
+    /// Insert a typed character at the caret.
+    Insert(char),
+    /// Delete the character before the caret.
+    Backspace,
+    /// Delete the character after the caret.
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    /// Return: the script accepts the text.
+    Submit,
+    /// Escape: the script discards the text.
+    Cancel,
+}
+
+impl TextEdit {
+    /// The edits of the characters a window received, in order: typed
+    /// characters and the editing keys it reports as control characters
+    /// (backspace, return, escape). Other control characters are skipped.
+    pub fn from_text(text: &str) -> Vec<TextEdit> {
+        text.chars().filter_map(|c| match c {
+            '\u{8}' => Some(TextEdit::Backspace),
+            '\r' | '\n' => Some(TextEdit::Submit),
+            '\u{1b}' => Some(TextEdit::Cancel),
+            c if c.is_control() => None,
+            c => Some(TextEdit::Insert(c)),
+        }).collect()
+    }
+}
+
+/// Text input buffer for the Itsy script (begin_text_input): a single line of
+/// text with a caret, edited by the keys typed while it is active. Submit and
+/// cancel are reported to the script for one frame; the script ends the input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextInput {
+    text        : Vec<char>,
+    /// Caret position in characters (0 = before the first character).
+    caret       : usize,
+    /// Maximum number of characters, 0 = unlimited.
+    max_len     : usize,
+    submitted   : bool,
+    cancelled   : bool,
+}
+
+impl TextInput {
+    /// Start editing `text` (truncated to `max_len` characters), caret at the end.
+    pub fn new(text: &str, max_len: usize) -> Self {
+        let mut text: Vec<char> = text.chars().collect();
+        if max_len > 0 {
+            text.truncate(max_len);
+        }
+        TextInput {
+            caret       : text.len(),
+            text        : text,
+            max_len     : max_len,
+            submitted   : false,
+            cancelled   : false,
+        }
+    }
+
+    /// The current text.
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    /// The text before the caret (to position the caret when drawing).
+    pub fn head(&self) -> String {
+        self.text[..self.caret].iter().collect()
+    }
+
+    /// Caret position in characters.
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// Return was pressed this frame.
+    pub fn submitted(&self) -> bool {
+        self.submitted
+    }
+
+    /// Escape was pressed this frame.
+    pub fn cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Clear the per-frame submit/cancel events (start of a frame).
+    pub fn clear_events(&mut self) {
+        self.submitted = false;
+        self.cancelled = false;
+    }
+
+    /// Apply one editing event.
+    pub fn apply(&mut self, edit: TextEdit) {
+        match edit {
+            TextEdit::Insert(c) => {
+                if self.max_len == 0 || self.text.len() < self.max_len {
+                    self.text.insert(self.caret, c);
+                    self.caret += 1;
+                }
+            }
+            TextEdit::Backspace => {
+                if self.caret > 0 {
+                    self.caret -= 1;
+                    self.text.remove(self.caret);
+                }
+            }
+            TextEdit::Delete => {
+                if self.caret < self.text.len() {
+                    self.text.remove(self.caret);
+                }
+            }
+            TextEdit::Left => self.caret = self.caret.saturating_sub(1),
+            TextEdit::Right => self.caret = (self.caret + 1).min(self.text.len()),
+            TextEdit::Home => self.caret = 0,
+            TextEdit::End => self.caret = self.text.len(),
+            TextEdit::Submit => self.submitted = true,
+            TextEdit::Cancel => self.cancelled = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TextInput, TextEdit};
+
+    fn type_text(input: &mut TextInput, text: &str) {
+        for c in text.chars() {
+            input.apply(TextEdit::Insert(c));
+        }
+    }
+
+    #[test]
+    fn window_text_keeps_edits_in_order() {
+        let edits = TextEdit::from_text("a\u{8}b\u{7f}\r");
+        assert_eq!(edits, vec![ TextEdit::Insert('a'), TextEdit::Backspace, TextEdit::Insert('b'), TextEdit::Submit ]);
+        let mut input = TextInput::new("", 0);
+        for edit in edits {
+            input.apply(edit);
+        }
+        assert_eq!(input.text(), "b");
+    }
+
+    #[test]
+    fn typing_inserts_at_caret() {
+        let mut input = TextInput::new("ac", 0);
+        input.apply(TextEdit::Left);
+        type_text(&mut input, "b");
+        assert_eq!(input.text(), "abc");
+        assert_eq!(input.head(), "ab");
+        input.apply(TextEdit::Home);
+        type_text(&mut input, "_");
+        input.apply(TextEdit::End);
+        type_text(&mut input, "!");
+        assert_eq!(input.text(), "_abc!");
+        assert_eq!(input.caret(), 5);
+    }
+
+    #[test]
+    fn backspace_and_delete_around_caret() {
+        let mut input = TextInput::new("abcd", 0);
+        input.apply(TextEdit::Left);
+        input.apply(TextEdit::Left);
+        input.apply(TextEdit::Backspace);
+        input.apply(TextEdit::Delete);
+        assert_eq!(input.text(), "ad");
+        assert_eq!(input.caret(), 1);
+        input.apply(TextEdit::Home);
+        input.apply(TextEdit::Backspace);
+        input.apply(TextEdit::Left);
+        assert_eq!(input.text(), "ad");
+        assert_eq!(input.caret(), 0);
+    }
+
+    #[test]
+    fn max_len_limits_text() {
+        let mut input = TextInput::new("toolong", 4);
+        assert_eq!(input.text(), "tool");
+        type_text(&mut input, "x");
+        assert_eq!(input.text(), "tool");
+        input.apply(TextEdit::Backspace);
+        type_text(&mut input, "xy");
+        assert_eq!(input.text(), "toox");
+    }
+
+    #[test]
+    fn submit_and_cancel_last_one_frame() {
+        let mut input = TextInput::new("name", 0);
+        input.apply(TextEdit::Submit);
+        assert!(input.submitted() && !input.cancelled());
+        input.clear_events();
+        input.apply(TextEdit::Cancel);
+        assert!(!input.submitted() && input.cancelled());
+        input.clear_events();
+        assert!(!input.submitted() && !input.cancelled());
+        assert_eq!(input.text(), "name");
+    }
+}
@@ -0,0 +1,163 @@
+use crate::prelude::*;
+use crate::audio::Bus;
+use crate::scripting::ScriptBudget;
+use crate::sound;
+use serde::{Serialize, Deserialize};
+use std::io;
+use std::path::Path;
+
+/// Settings changed by the player (options menu).
+pub const USER_PATH: &str = "user/settings.toml";
+
+/// Volume and mute state of one audio bus.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BusSettings {
+    /// Volume 0..=1.
+    pub volume  : f32,
+    pub muted   : bool,
+}
+
+impl Default for BusSettings {
+    fn default() -> Self {
+        BusSettings { volume: 1.0, muted: false }
+    }
+}
+
+/// Audio bus settings (see `audio::Bus`) and the sound cache size.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master  : BusSettings,
+    pub sfx     : BusSettings,
+    pub music   : BusSettings,
+    pub ui      : BusSettings,
+    /// Memory for decoded sounds in MiB (all cached sounds together); sounds
+    /// that don't fit stay encoded and are decoded while playing.
+    pub sound_cache_mb: usize,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master  : BusSettings::default(),
+            sfx     : BusSettings::default(),
+            music   : BusSettings::default(),
+            ui      : BusSettings::default(),
+            sound_cache_mb: sound::DECODE_LIMIT / (1024 * 1024),
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn bus(&self, bus: Bus) -> &BusSettings {
+        match bus {
+            Bus::Master => &self.master,
+            Bus::Sfx => &self.sfx,
+            Bus::Music => &self.music,
+            Bus::Ui => &self.ui,
+        }
+    }
+
+    pub fn bus_mut(&mut self, bus: Bus) -> &mut BusSettings {
+        match bus {
+            Bus::Master => &mut self.master,
+            Bus::Sfx => &mut self.sfx,
+            Bus::Music => &mut self.music,
+            Bus::Ui => &mut self.ui,
+        }
+    }
+}
+
+/// Per-frame execution limits of the Itsy VM (see `scripting::ScriptBudget`).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptSettings {
+    /// Maximum wall clock time per frame in milliseconds, 0 = no time limit.
+    pub max_time_ms     : u64,
+    /// Maximum number of VM instructions per frame.
+    pub max_instructions: u64,
+}
+
+impl Default for ScriptSettings {
+    fn default() -> Self {
+        let budget = ScriptBudget::default();
+        ScriptSettings {
+            max_time_ms     : budget.max_time.map_or(0, |max_time| max_time.as_millis() as u64),
+            max_instructions: budget.max_instructions,
+        }
+    }
+}
+
+impl ScriptSettings {
+    /// The VM budget with these limits.
+    pub fn budget(&self) -> ScriptBudget {
+        ScriptBudget {
+            max_time        : (self.max_time_ms > 0).then(|| Duration::from_millis(self.max_time_ms)),
+            max_instructions: self.max_instructions,
+        }
+    }
+}
+
+/// Player settings, saved to USER_PATH. Missing values (or a missing file)
+/// use the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    /// Script watchdog limits (not in the options menu, edit the file).
+    pub script: ScriptSettings,
+}
+
+impl Settings {
+    /// Loads the player's settings, or the defaults if there are none.
+    pub fn load() -> Settings {
+        if !Path::new(USER_PATH).is_file() {
+            return Settings::default();
+        }
+        fs::read_to_string(USER_PATH).map_err(|e| e.to_string()).and_then(|source| Self::parse(&source)).unwrap_or_else(|e| {
+            eprintln!("settings: ignoring '{}': {}", USER_PATH, e);
+            Settings::default()
+        })
+    }
+
+    /// Parses settings from TOML source.
+    pub fn parse(source: &str) -> Result<Settings, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    /// Saves the settings to USER_PATH.
+    pub fn save(&self) -> io::Result<()> {
+        let source = toml::to_string_pretty(self).map_err(io::Error::other)?;
+        if let Some(dir) = Path::new(USER_PATH).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(USER_PATH, source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_values_use_defaults() {
+        let settings = Settings::parse("[audio.music]\nvolume = 0.5\n").unwrap();
+        assert_eq!(settings.audio.music, BusSettings { volume: 0.5, muted: false });
+        assert_eq!(settings.audio.master, BusSettings::default());
+        assert_eq!(Settings::parse("").unwrap(), Settings::default());
+        let settings = Settings::parse("[script]\nmax_time_ms = 250\n").unwrap();
+        assert_eq!(settings.script.budget().max_time, Some(Duration::from_millis(250)));
+        assert_eq!(Settings::parse("[script]\nmax_time_ms = 0\n").unwrap().script.budget().max_time, None);
+        assert_eq!(settings.script.max_instructions, ScriptBudget::default().max_instructions);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut settings = Settings::default();
+        settings.audio.bus_mut(Bus::Sfx).volume = 0.3;
+        settings.audio.bus_mut(Bus::Ui).muted = true;
+        let saved = toml::to_string_pretty(&settings).unwrap();
+        assert_eq!(Settings::parse(&saved).unwrap(), settings);
+    }
+}
@@ -1,28 +1,119 @@
 use crate::prelude::*;
 use std::io;
 use std::convert::AsRef;
-use rodio::Decoder;
+use rodio::{Decoder, Source};
+use rodio::buffer::SamplesBuffer;
 
-pub struct Sound (Arc<Vec<u8>>);
+/// Default limit for the decoded size of sounds in bytes (about 3 minutes of
+/// 44.1 kHz stereo; see `AudioSettings::sound_cache_mb`); longer files stay
+/// encoded and are decoded while playing.
+pub const DECODE_LIMIT: usize = 64 * 1024 * 1024;
 
-impl AsRef<[u8]> for Sound {
+/// Encoded file data, shared by the decoders of all plays.
+struct Encoded (Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for Encoded {
     fn as_ref(&self) -> &[u8] {
         &self.0
     }
 }
 
+/// A sound effect, decoded once into memory so plays only clone the sample
+/// buffer (no decode cost or latency when played).
+pub enum Sound {
+    /// Decoded samples (shared between plays) and their number.
+    Decoded { buffer: SamplesBuffer, samples: usize },
+    /// Encoded file data, decoded on every play (decoded size above the limit).
+    Encoded(Arc<Vec<u8>>),
+}
+
 impl Sound {
+    /// Load and decode a sound file (up to DECODE_LIMIT).
     pub fn load(filename: &str) -> io::Result<Sound> {
+        Self::load_limited(filename, DECODE_LIMIT)
+    }
+
+    /// Load a sound file, decoding it if its decoded size is at most `limit` bytes.
+    pub fn load_limited(filename: &str, limit: usize) -> io::Result<Sound> {
         use std::fs::File;
         let mut buf = Vec::new();
         let mut file = File::open(filename)?;
         file.read_to_end(&mut buf)?;
-        Ok(Sound(Arc::new(buf)))
+        Self::decode(buf, limit)
+    }
+
+    /// Decode encoded file data (see `load_limited`). Files whose length is
+    /// known to be above the limit are not decoded at all; otherwise decoding
+    /// stops once the limit is exceeded.
+    pub fn decode(data: Vec<u8>, limit: usize) -> io::Result<Sound> {
+        let data = Arc::new(data);
+        let decoder = Self::decoder(&data)?;
+        let (channels, sample_rate) = (decoder.channels(), decoder.sample_rate());
+        let max_samples = limit / std::mem::size_of::<rodio::Sample>();
+        if let Some(duration) = decoder.total_duration() {
+            let samples = duration.as_secs_f64() * sample_rate.get() as f64 * channels.get() as f64;
+            if samples > max_samples as f64 {
+                return Ok(Sound::Encoded(data));
+            }
+        }
+        let samples: Vec<rodio::Sample> = decoder.take(max_samples + 1).collect();
+        if samples.len() > max_samples {
+            Ok(Sound::Encoded(data))
+        } else {
+            Ok(Sound::Decoded { samples: samples.len(), buffer: SamplesBuffer::new(channels, sample_rate, samples) })
+        }
+    }
+
+    fn decoder(data: &Arc<Vec<u8>>) -> io::Result<Decoder<io::Cursor<Encoded>>> {
+        Decoder::try_from(io::Cursor::new(Encoded(data.clone()))).map_err(io::Error::other)
+    }
+
+    /// A source playing the sound from the start.
+    pub fn source(&self) -> Box<dyn Source + Send> {
+        match self {
+            Sound::Decoded { buffer, .. } => Box::new(buffer.clone()),
+            // decoded successfully once when loaded
+            Sound::Encoded(data) => Box::new(Self::decoder(data).unwrap()),
+        }
+    }
+
+    /// Memory used by the sound in bytes.
+    pub fn memory(&self) -> usize {
+        match self {
+            Sound::Decoded { samples, .. } => samples * std::mem::size_of::<rodio::Sample>(),
+            Sound::Encoded(data) => data.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "res/sound/projectile/pew2.ogg";
+
+    #[test]
+    fn decodes_into_shared_samples() {
+        let sound = Sound::load(FILE).unwrap();
+        let Sound::Decoded { buffer, .. } = &sound else { panic!("expected a decoded sound") };
+        let samples: Vec<rodio::Sample> = buffer.clone().collect();
+        assert!(!samples.is_empty());
+        assert_eq!(sound.memory(), samples.len() * std::mem::size_of::<rodio::Sample>());
+        assert_eq!(samples.len() % buffer.channels().get() as usize, 0);
+        // every play yields the same samples
+        assert_eq!(sound.source().collect::<Vec<_>>(), samples);
     }
-    fn cursor(&self) -> io::Cursor<Sound> {
-        io::Cursor::new(Sound(self.0.clone()))
+
+    #[test]
+    fn large_sounds_stay_encoded() {
+        let sound = Sound::load_limited(FILE, 1024).unwrap();
+        assert!(matches!(sound, Sound::Encoded(_)));
+        assert_eq!(sound.memory(), fs::metadata(FILE).unwrap().len() as usize);
+        assert_eq!(sound.source().count(), Sound::load(FILE).unwrap().source().count());
     }
-    pub fn decoder(&self) -> Decoder<io::Cursor<Sound>> {
-        Decoder::try_from(self.cursor()).unwrap()
+
+    #[test]
+    fn invalid_data_is_an_error() {
+        assert!(Sound::decode(b"not a sound".to_vec(), DECODE_LIMIT).is_err());
     }
 }
@@ -0,0 +1,139 @@
+use crate::prelude::*;
+
+/// Font used for the Rust side debug text (bundled, see res/font/LICENSE).
+pub const DEFAULT_FONT: &str = "res/font/DejaVuSans.ttf";
+
+/// Reference text measured for the line height.
+const LINE_REFERENCE: &str = "Mg";
+
+/// Horizontal text alignment relative to the text position.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl HAlign {
+    /// Alignment from its script ID (`Api::ALIGN_LEFT` etc.); unknown IDs align left.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => HAlign::Center,
+            2 => HAlign::Right,
+            _ => HAlign::Left,
+        }
+    }
+}
+
+/// Vertical text alignment relative to the text position.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl VAlign {
+    /// Alignment from its script ID (`Api::ALIGN_TOP` etc.); unknown IDs align to the top.
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => VAlign::Middle,
+            2 => VAlign::Bottom,
+            _ => VAlign::Top,
+        }
+    }
+}
+
+/// Load a font file at `size` pixels.
+pub fn load_font(context: &Context, path: &str, size: f32) -> Result<Font, String> {
+    let font = Font::from_file(context, path).map_err(|e| format!("cannot load '{}': {:?}", path, e))?;
+    Ok(font.clone_with_size(size))
+}
+
+/// Split `text` into lines: at line breaks and, if `max_width` is positive,
+/// between words so no line is wider than `max_width` (a single word wider
+/// than that gets a line of its own). `width` measures a line in pixels.
+pub fn wrap(text: &str, max_width: f32, width: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        if max_width <= 0.0 {
+            lines.push(paragraph.to_string());
+            continue;
+        }
+        let mut line = String::new();
+        for word in paragraph.split(' ').filter(|word| !word.is_empty()) {
+            if !line.is_empty() {
+                let candidate = format!("{} {}", line, word);
+                if width(&candidate) <= max_width {
+                    line = candidate;
+                    continue;
+                }
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Lines of `text` (see `wrap`) with the positions to draw them at, so the
+/// text block is aligned to `position`. `measure` returns the size of a line
+/// in pixels.
+pub fn layout(text: &str, position: (f32, f32), align: (HAlign, VAlign), max_width: f32, measure: impl Fn(&str) -> (f32, f32)) -> Vec<(String, (f32, f32))> {
+    let lines = wrap(text, max_width, |line| measure(line).0);
+    let line_height = measure(LINE_REFERENCE).1;
+    let height = line_height * lines.len() as f32;
+    let top = match align.1 {
+        VAlign::Top => position.1,
+        VAlign::Middle => position.1 - height * 0.5,
+        VAlign::Bottom => position.1 - height,
+    };
+    lines.into_iter().enumerate().map(|(index, line)| {
+        let width = measure(&line).0;
+        let left = match align.0 {
+            HAlign::Left => position.0,
+            HAlign::Center => position.0 - width * 0.5,
+            HAlign::Right => position.0 - width,
+        };
+        (line, (left, top + index as f32 * line_height))
+    }).collect()
+}
+
+/// Size of `text` laid out with `layout`: the widest line and the height of all lines.
+pub fn measure(text: &str, max_width: f32, measure: impl Fn(&str) -> (f32, f32)) -> (f32, f32) {
+    let lines = wrap(text, max_width, |line| measure(line).0);
+    let width = lines.iter().map(|line| measure(line).0).fold(0.0, f32::max);
+    (width, measure(LINE_REFERENCE).1 * lines.len() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 pixels per character, 20 per line.
+    fn monospace(line: &str) -> (f32, f32) {
+        (line.chars().count() as f32 * 10.0, 20.0)
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        let width = |line: &str| monospace(line).0;
+        assert_eq!(wrap("New Game", 0.0, width), vec!["New Game"]);
+        assert_eq!(wrap("one two three four", 90.0, width), vec!["one two", "three", "four"]);
+        assert_eq!(wrap("a  b\nlongword c", 50.0, width), vec!["a b", "longword", "c"]);
+        assert_eq!(wrap("", 50.0, width), vec![""]);
+    }
+
+    #[test]
+    fn aligns_text_block() {
+        let lines = layout("ab\nabcd", (100.0, 100.0), (HAlign::Center, VAlign::Middle), 0.0, monospace);
+        assert_eq!(lines, vec![ ("ab".to_string(), (90.0, 80.0)), ("abcd".to_string(), (80.0, 100.0)) ]);
+        let lines = layout("abc", (100.0, 100.0), (HAlign::Right, VAlign::Bottom), 0.0, monospace);
+        assert_eq!(lines, vec![ ("abc".to_string(), (70.0, 80.0)) ]);
+        assert_eq!(measure("one two three", 90.0, monospace), (70.0, 40.0));
+        assert_eq!((HAlign::from_id(2), VAlign::from_id(7)), (HAlign::Right, VAlign::Top));
+    }
+}
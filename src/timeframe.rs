@@ -9,6 +9,14 @@ pub struct Timeframe {
     factor_value        : f64,
     lerp_factor_end     : Option<Instant>,
     lerp_factor_value   : f64,
+    /// Current real time when simulated (see `simulate`).
+    simulated_now       : Option<Instant>,
+}
+
+impl Default for Timeframe {
+    fn default() -> Timeframe {
+        Timeframe::new()
+    }
 }
 
 impl Timeframe {
@@ -22,6 +30,32 @@ impl Timeframe {
             factor_value        : 1.0,
             lerp_factor_end     : None,
             lerp_factor_value   : 1.0,
+            simulated_now       : None,
+        }
+    }
+
+    /**
+     * Creates a new timeframe on a simulated clock, which only advances on advance().
+     */
+    pub fn simulated() -> Timeframe {
+        let mut timeframe = Timeframe::new();
+        timeframe.simulated_now = Some(timeframe.factor_start);
+        timeframe
+    }
+
+    /**
+     * Returns whether the timeframe runs on a simulated clock.
+     */
+    pub fn is_simulated(self: &Self) -> bool {
+        self.simulated_now.is_some()
+    }
+
+    /**
+     * Advances the simulated clock by the given real duration. No effect on a real time timeframe.
+     */
+    pub fn advance(self: &mut Self, real_duration: Duration) {
+        if let Some(now) = &mut self.simulated_now {
+            *now += real_duration;
         }
     }
 
@@ -30,19 +64,31 @@ impl Timeframe {
      */
     pub fn rate(self: &Self) -> f64 {
         if self.lerp_factor_end.is_some() {
-            let now = Instant::now();
+            let now = self.now();
             self.factor_value + (self.lerp_factor_value - self.factor_value) * self.lerp_progress(now)
         } else {
             self.factor_value
         }
     }
 
+    /**
+     * Returns the rate time is heading to: the target of the current lerp, or
+     * the current rate if not lerping.
+     */
+    pub fn target_rate(self: &Self) -> f64 {
+        if self.lerp_factor_end.is_some() {
+            self.lerp_factor_value
+        } else {
+            self.factor_value
+        }
+    }
+
     /**
      * Returns the elapsed duration of time since the timeframe began.
      */
     pub fn elapsed(self: &Self) -> Duration {
         if let Some(lerp_factor_end) = self.lerp_factor_end {
-            let now = Instant::now();
+            let now = self.now();
 
             // linear segment
             let factor = self.rate();
@@ -57,11 +103,11 @@ impl Timeframe {
             self.total + Self::duration_from_secs(total)
 
         } else if self.factor_value == 1.0 {
-            self.total + (Instant::now() - self.factor_start)
+            self.total + (self.now() - self.factor_start)
         } else if self.factor_value == 0.0 {
             self.total
         } else {
-            self.total + Self::duration_mul_f64(Instant::now() - self.factor_start, self.factor_value)
+            self.total + Self::duration_mul_f64(self.now() - self.factor_start, self.factor_value)
         }
     }
 
@@ -85,7 +131,7 @@ impl Timeframe {
     pub fn set_rate(self: &mut Self, rate: f64) {
         if self.lerp_factor_end.is_some() || rate != self.factor_value {
             self.total = self.elapsed();
-            self.factor_start = Instant::now();
+            self.factor_start = self.now();
             self.factor_value = rate;
             self.lerp_factor_end = None;
         }
@@ -100,7 +146,7 @@ impl Timeframe {
 
         if (is_lerp && target_rate != self.lerp_factor_value) || (!is_lerp && target_rate != self.factor_value) {
 
-            let now = Instant::now();
+            let now = self.now();
             let current_factor = self.rate();
 
             // if still lerping, compute expected rate of change (that would have occured without unfinished lerp) and
@@ -124,7 +170,7 @@ impl Timeframe {
      */
     pub fn is_lerp(self: &Self) -> bool {
         if let Some(lerp_factor_end) = self.lerp_factor_end {
-            lerp_factor_end < Instant::now()
+            lerp_factor_end < self.now()
         } else {
             false
         }
@@ -136,7 +182,7 @@ impl Timeframe {
      */
     pub fn set_elapsed(self: &mut Self, elapsed: Duration) {
         self.total = elapsed;
-        self.factor_start = Instant::now();
+        self.factor_start = self.now();
         if self.lerp_factor_end.is_some() {
             self.factor_value = self.lerp_factor_value;
             self.lerp_factor_end = None;
@@ -161,6 +207,13 @@ impl Timeframe {
         Self::duration_from_secs(Self::duration_to_secs(duration) * multiplier)
     }
 
+    /**
+     * Returns the current real (or simulated) time.
+     */
+    pub fn now(self: &Self) -> Instant {
+        self.simulated_now.unwrap_or_else(Instant::now)
+    }
+
     /**
      * Returns progress from 0 to 1 of the current lerp
      */
@@ -177,4 +230,20 @@ impl Timeframe {
             panic!("lerp_progress called outside of lerp");
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulated_time_only_advances_on_advance() {
+        let mut timeframe = Timeframe::simulated();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(timeframe.elapsed(), Duration::ZERO);
+        timeframe.advance(Duration::from_millis(100));
+        assert_eq!(timeframe.elapsed(), Duration::from_millis(100));
+        timeframe.set_rate(0.5);
+        timeframe.advance(Duration::from_millis(100));
+        assert!((timeframe.elapsed_f64() - 0.15).abs() < 1e-6);
+    }
+}
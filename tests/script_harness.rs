@@ -0,0 +1,500 @@
+/// Integration tests: run the Itsy scripts frame by frame against a scripted
+/// context (`ScriptHarness`) and assert on the recorded API operations.
+
+use spacegame::scripting::{Api, ApiOp, EntityData, ScriptHarness, ScriptBudget, ScriptError, TextEdit};
+
+const PLAYER_ID: u64 = 100;
+const POWERUP_ID: u64 = 200;
+
+fn entity(script_type: u16, x: f32, y: f32) -> EntityData {
+    EntityData { script_type, position: (x, y), alive: true, hitpoints: 100.0, ..EntityData::default() }
+}
+
+fn spawned_types(ops: &[ApiOp]) -> Vec<u16> {
+    ops.iter().filter_map(|op| match op {
+        ApiOp::Spawn(req) => Some(req.entity_type),
+        _ => None,
+    }).collect()
+}
+
+fn count(ops: &[ApiOp], matches: impl Fn(&ApiOp) -> bool) -> usize {
+    ops.iter().filter(|op| matches(op)).count()
+}
+
+/// ID of the first filter of `kind` created in `ops` (IDs are assigned in
+/// creation order, starting at 1).
+fn filter_id(ops: &[ApiOp], kind: u32) -> u32 {
+    ops.iter().filter(|op| matches!(op, ApiOp::CreateFilter { .. }))
+        .position(|op| *op == ApiOp::CreateFilter { kind }).unwrap() as u32 + 1
+}
+
+/// Values set for a filter parameter in `ops`.
+fn filter_param(ops: &[ApiOp], filter: u32, param: u32) -> Vec<f32> {
+    ops.iter().filter_map(|op| match op {
+        ApiOp::SetFilterParam { filter_id, param: p, value } if *filter_id == filter && *p == param => Some(*value),
+        _ => None,
+    }).collect()
+}
+
+fn game() -> ScriptHarness {
+    ScriptHarness::new("res/script/game.itsy").unwrap()
+}
+
+#[test]
+fn first_frame_sets_up_level() {
+    let mut script = game();
+    let ops = script.frame().unwrap();
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::CreateLayer { .. })), 4);
+    assert_eq!(spawned_types(&ops), vec![Api::ET_PLAYER]);
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::StartTimer { .. })), 2);
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::CreateFilter { .. })), 5);
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::AddRenderLayer { filter_id, .. } if *filter_id != Api::FILTER_NONE)), 4);
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::AddPassFilter { .. })), 1);
+    assert!(ops.contains(&ApiOp::SetToneMapping(Api::TONE_MAPPING_ACES)));
+}
+
+#[test]
+fn player_damage_pulses_effects() {
+    let mut script = game();
+    let ops = script.frame().unwrap();
+    let grading = filter_id(&ops, Api::FILTER_COLOR_GRADING);
+    let damage_lut = script.context.lut_list.iter().position(|lut| lut == "res/lut/damage.png").unwrap() as u32;
+    assert!(ops.contains(&ApiOp::SetFilterLut { filter_id: grading, lut_id: damage_lut }));
+    script.add_entity(PLAYER_ID, entity(Api::ET_PLAYER, 230.0, 350.0));
+    script.frame().unwrap();
+
+    script.context.entity_data.get_mut(&PLAYER_ID).unwrap().hitpoints = 90.0;
+    script.frame().unwrap();
+    let strength = filter_param(&script.frames(1, 0.1).unwrap(), grading, Api::FILTER_PARAM_STRENGTH);
+    assert!(strength.len() == 1 && strength[0] > 0.0 && strength[0] < 1.0);
+}
+
+#[test]
+fn explosions_pulse_bloom() {
+    const ASTEROID_ID: u64 = 7;
+    let mut script = game();
+    let ops = script.frame().unwrap();
+    let bloom = filter_id(&ops, Api::FILTER_BLOOM);
+    script.add_entity(ASTEROID_ID, entity(Api::ET_ASTEROID, 400.0, 300.0));
+    script.frame().unwrap();
+
+    script.context.dying_entities = vec![ ASTEROID_ID ];
+    let ops = script.frame().unwrap();
+    assert_eq!(spawned_types(&ops), vec![Api::ET_EXPLOSION]);
+
+    // brightness raised above its resting 0.15, then decaying
+    let first = filter_param(&script.frames(1, 0.1).unwrap(), bloom, Api::FILTER_PARAM_BRIGHTNESS);
+    assert!(first.len() == 1 && first[0] > 0.15);
+    let ops = script.frames(1, 0.1).unwrap();
+    let second = filter_param(&ops, bloom, Api::FILTER_PARAM_BRIGHTNESS);
+    assert!(second.len() == 1 && second[0] < first[0]);
+    let exposure: Vec<f32> = ops.iter().filter_map(|op| match op {
+        ApiOp::SetExposure(exposure) => Some(*exposure),
+        _ => None,
+    }).collect();
+    assert!(exposure.len() == 1 && exposure[0] > 1.2);
+}
+
+#[test]
+fn powerups_use_shimmer_program_unless_it_failed() {
+    let mut script = game();
+    let ops = script.frame().unwrap();
+    let shimmer = script.context.shader_list.iter().position(|shader| shader == "res/shader/shimmer.wgsl").unwrap() as u32;
+    assert_eq!(count(&ops, |op| *op == ApiOp::CreateProgram { shader_id: shimmer }), 1);
+    script.add_entity(POWERUP_ID, entity(Api::ET_POWERUP_DUAL, 400.0, 300.0));
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::SetEntityProgram { entity_id: POWERUP_ID, program_id: 1 }));
+
+    let mut script = game();
+    script.frame().unwrap();
+    script.context.program_errors.insert(1, "error: expected ';'".to_string());
+    script.add_entity(POWERUP_ID, entity(Api::ET_POWERUP_DUAL, 400.0, 300.0));
+    let ops = script.frame().unwrap();
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::SetEntityProgram { .. } | ApiOp::SetProgramUniform { .. })), 0);
+}
+
+#[test]
+fn invalid_program_keeps_later_program_ids_in_sync() {
+    let mut script = ScriptHarness::from_source("res/script", "
+        use Api::{create_program, set_program_uniform};
+        fn main() {
+            let invalid = create_program(9999);
+            let valid = create_program(0);
+            set_program_uniform(invalid, \"amount\", 1.0);
+            set_program_uniform(valid, \"amount\", 2.0);
+        }
+    ").unwrap();
+    let ops = script.frame().unwrap();
+    // both are queued, so the Rust side numbers them 1 and 2 as well
+    assert_eq!(ops[..2], [ ApiOp::CreateProgram { shader_id: 9999 }, ApiOp::CreateProgram { shader_id: 0 } ]);
+    assert!(ops.contains(&ApiOp::SetProgramUniform { program_id: 2, name: "amount".to_string(), value: 2.0 }));
+    assert!(script.context.program_errors.contains_key(&1));
+    assert!(!script.context.program_errors.contains_key(&2));
+}
+
+#[test]
+fn runaway_loop_is_interrupted_and_script_restarts() {
+    let mut script = ScriptHarness::from_source("res/script", "
+        use Api::{get_game_time, set_debug_layer, start_timer};
+        fn main() {
+            let timer = start_timer(1.0, 1.0, 0);
+            while (true) {
+                let time = get_game_time();
+                set_debug_layer(timer);
+                if (time >= 1.0 && time < 2.0) {
+                    while (true) { }
+                }
+                suspend;
+            }
+        }
+    ").unwrap();
+    script.set_budget(ScriptBudget { max_instructions: 100_000, ..ScriptBudget::default() });
+    let start_ops = |game_time| vec![
+        ApiOp::StartTimer { id: 1, delay: 1.0, interval: 1.0, entity_id: 0, game_time },
+        ApiOp::SetDebugLayer(1),
+    ];
+    assert_eq!(script.frame().unwrap(), start_ops(0.0));
+
+    // the interrupted frame's operations are dropped with the error
+    script.advance(1.0);
+    assert!(matches!(script.frame(), Err(ScriptError::BudgetExceeded { instructions: 100_000, .. })));
+
+    // the next frame runs `main` again, with the timer IDs starting over
+    script.advance(1.0);
+    assert_eq!(script.frame().unwrap(), start_ops(2.0));
+}
+
+#[test]
+fn screenshot_actions_request_captures() {
+    let mut script = game();
+    script.frame().unwrap();
+    script.press("screenshot");
+    assert!(script.frame().unwrap().contains(&ApiOp::TakeScreenshot { overlay: true }));
+    script.release_all();
+    script.press("screenshot_clean");
+    assert!(script.frame().unwrap().contains(&ApiOp::TakeScreenshot { overlay: false }));
+}
+
+#[test]
+fn spawner_timers_spawn_asteroids() {
+    let mut script = game();
+    let ops = script.frame().unwrap();
+    let asteroid_timer = ops.iter().find_map(|op| match op {
+        ApiOp::StartTimer { id, delay, .. } if *delay == 4.0 => Some(*id),
+        _ => None,
+    }).expect("asteroid spawner timer");
+
+    // no timer event: nothing spawns
+    let ops = script.frames(1, 1.0 / 60.0).unwrap();
+    assert!(spawned_types(&ops).is_empty());
+
+    script.context.timer_events = vec![ (asteroid_timer, 0) ];
+    let ops = script.frames(1, 1.0 / 60.0).unwrap();
+    assert_eq!(spawned_types(&ops), vec![Api::ET_ASTEROID]);
+}
+
+#[test]
+fn single_weapon_fires_one_shot_with_cooldown() {
+    let mut script = game();
+    script.frame().unwrap();
+    script.add_entity(PLAYER_ID, entity(Api::ET_PLAYER, 230.0, 350.0));
+    script.hold("fire");
+
+    script.advance(1.0);
+    let ops = script.frame().unwrap();
+    assert_eq!(spawned_types(&ops), vec![Api::ET_PROJECTILE]);
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::PlayGroup { name, position: Some((230.0, 350.0)) } if name == "projectile/pew")), 1);
+    let cooldown = ops.iter().find_map(|op| match op {
+        ApiOp::StartTimer { id, delay, interval, .. } if *delay == 0.2 && *interval == 0.0 => Some(*id),
+        _ => None,
+    }).expect("weapon cooldown timer");
+
+    // still cooling down until the cooldown timer (0.2 s) fires
+    let ops = script.frames(2, 0.1).unwrap();
+    assert!(spawned_types(&ops).is_empty());
+    script.context.timer_events = vec![ (cooldown, 0) ];
+    let ops = script.frames(1, 0.1).unwrap();
+    assert_eq!(spawned_types(&ops), vec![Api::ET_PROJECTILE]);
+}
+
+#[test]
+fn closing_the_menu_does_not_fire() {
+    let mut script = game();
+    script.frame().unwrap();
+    script.add_entity(PLAYER_ID, entity(Api::ET_PLAYER, 230.0, 350.0));
+    script.press("menu_toggle");
+    script.frame().unwrap();
+    script.release_all();
+
+    // the click closing the menu, and holding it afterwards, does not shoot
+    script.press("menu_toggle");
+    script.hold("fire");
+    let ops = script.frames(1, 1.0).unwrap();
+    assert!(ops.contains(&ApiOp::ResumeTime));
+    assert!(spawned_types(&ops).is_empty());
+    script.release_all();
+    script.hold("fire");
+    assert!(spawned_types(&script.frames(1, 1.0).unwrap()).is_empty());
+
+    // a new press does
+    script.release_all();
+    script.frames(1, 1.0).unwrap();
+    script.press("fire");
+    assert_eq!(spawned_types(&script.frames(1, 1.0).unwrap()), vec![Api::ET_PROJECTILE]);
+}
+
+/// Depths of the entities of `entity_type` spawned in `ops`.
+fn spawned_depths(ops: &[ApiOp], entity_type: u16) -> Vec<f32> {
+    ops.iter().filter_map(|op| match op {
+        ApiOp::Spawn(req) if req.entity_type == entity_type => Some(req.depth),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn explosions_draw_above_shots() {
+    const ASTEROID_ID: u64 = 7;
+    let mut script = game();
+    script.frame().unwrap();
+    script.add_entity(PLAYER_ID, entity(Api::ET_PLAYER, 230.0, 350.0));
+    script.add_entity(ASTEROID_ID, entity(Api::ET_ASTEROID, 400.0, 300.0));
+    script.hold("fire");
+    script.advance(1.0);
+    let shots = spawned_depths(&script.frame().unwrap(), Api::ET_PROJECTILE);
+
+    script.context.dying_entities = vec![ ASTEROID_ID ];
+    let explosions = spawned_depths(&script.frame().unwrap(), Api::ET_EXPLOSION);
+    assert!(shots.len() == 1 && explosions.len() == 1);
+    assert!(explosions[0] > shots[0]);
+}
+
+#[test]
+fn triple_powerup_changes_fire_pattern() {
+    let mut script = game();
+    script.frame().unwrap();
+    script.add_entity(PLAYER_ID, entity(Api::ET_PLAYER, 230.0, 350.0));
+    script.add_entity(POWERUP_ID, entity(Api::ET_POWERUP_TRIPLE, 240.0, 350.0));
+
+    // pick up (within the 36 px pickup radius)
+    let ops = script.frames(1, 1.0).unwrap();
+    assert!(ops.contains(&ApiOp::Despawn(POWERUP_ID)));
+
+    script.hold("fire");
+    let ops = script.frames(1, 1.0).unwrap();
+    assert_eq!(spawned_types(&ops), vec![Api::ET_PROJECTILE; 3]);
+}
+
+fn menu() -> ScriptHarness {
+    ScriptHarness::from_source("res/script", "
+        mod menu;
+        fn main() {
+            let state = menu::MenuState { group: \"\", index: 0 };
+            while (true) {
+                state = menu::menu_process(state, 0);
+                suspend;
+            }
+        }
+    ").unwrap()
+}
+
+#[test]
+fn escape_opens_menu_and_pauses() {
+    let mut script = menu();
+    assert!(script.frame().unwrap().is_empty());
+
+    script.press("menu_toggle");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::PauseTime));
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::WriteText { msg, .. } if msg == "New Game")));
+
+    script.release_all();
+    let ops = script.frame().unwrap();
+    assert!(!ops.contains(&ApiOp::ResumeTime));
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::WriteText { .. })));
+
+    script.press("menu_toggle");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::ResumeTime));
+}
+
+#[test]
+fn menu_items_are_centered_in_menu_font() {
+    let mut script = menu();
+    script.context.screen_size = (1280, 720);
+    script.press("menu_toggle");
+    let ops = script.frame().unwrap();
+    assert_eq!(script.context.font_sizes, vec![ 80.0 ]);
+    let item = ops.iter().find_map(|op| match op {
+        ApiOp::WriteText { font_id, msg, position, align, .. } if msg == "New Game" => Some((*font_id, *position, *align)),
+        _ => None,
+    });
+    assert_eq!(item, Some((1, (640.0, 72.0), (Api::ALIGN_CENTER, Api::ALIGN_TOP))));
+
+    // the font is created once
+    script.release_all();
+    let ops = script.frame().unwrap();
+    assert_eq!(count(&ops, |op| matches!(op, ApiOp::CreateFont { .. })), 0);
+}
+
+#[test]
+fn menu_navigation_runs_selected_action() {
+    let mut script = menu();
+    script.press("menu_toggle");
+    script.frame().unwrap();
+    script.release_all();
+
+    script.press("menu_down");
+    script.frames(3, 0.0).unwrap();
+
+    script.release_all();
+    script.press("menu_select");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::RequestExit), "fourth item is Quit Game");
+}
+
+#[test]
+fn controls_menu_rebinds_selected_action() {
+    let mut script = menu();
+    script.context.action_bindings.insert("fire".to_string(), "Mouse1 / LControl".to_string());
+    script.press("menu_toggle");
+    script.frame().unwrap();
+    script.release_all();
+
+    // main -> Options (third item) -> Controls (fourth item)
+    script.press("menu_down");
+    script.frames(2, 0.0).unwrap();
+    script.release_all();
+    script.press("menu_select");
+    script.frame().unwrap();
+    script.release_all();
+    script.press("menu_down");
+    script.frames(3, 0.0).unwrap();
+    script.release_all();
+    script.press("menu_select");
+    script.frame().unwrap();
+    script.release_all();
+    let ops = script.frame().unwrap();
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::WriteText { msg, .. } if msg == "Fire: Mouse1 / LControl")));
+
+    script.press("menu_select");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::RebindAction("fire".to_string())));
+}
+
+#[test]
+fn mouse_click_runs_item_and_frees_cursor() {
+    let mut script = menu();
+    script.context.screen_size = (1280, 720);
+    script.press("menu_toggle");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::GrabCursor(false)));
+    assert!(ops.contains(&ApiOp::SetCursorVisible(true)));
+    script.release_all();
+
+    // "Quit Game" is drawn centered on (0.5, 0.45) of the screen
+    script.context.mouse_pos = (700, 340);
+    script.context.mouse_delta = (5, 0);
+    script.press("menu_click");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::RequestExit));
+}
+
+#[test]
+fn name_entry_edits_and_stores_player_name() {
+    let mut script = menu();
+    script.press("menu_toggle");
+    script.frame().unwrap();
+    script.release_all();
+
+    // main -> Options (third item) -> Name (fifth item)
+    script.press("menu_down");
+    script.frames(2, 0.0).unwrap();
+    script.release_all();
+    script.press("menu_select");
+    script.frame().unwrap();
+    script.release_all();
+    script.press("menu_down");
+    script.frames(4, 0.0).unwrap();
+    script.release_all();
+    script.press("menu_select");
+    script.frame().unwrap();
+    script.release_all();
+    assert!(script.context.text_input.is_some());
+
+    script.text_edit(TextEdit::Home);
+    script.text_edit(TextEdit::Delete);
+    script.type_text("X");
+    let ops = script.frame().unwrap();
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::WriteText { msg, .. } if msg == "Name: Xlayer")));
+
+    script.text_edit(TextEdit::Submit);
+    script.frame().unwrap();
+    assert!(script.context.text_input.is_none());
+    let ops = script.frame().unwrap();
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::WriteText { msg, .. } if msg == "Name: Xlayer")));
+}
+
+#[test]
+fn audio_menu_adjusts_and_mutes_buses() {
+    let mut script = menu();
+    script.press("menu_toggle");
+    script.frame().unwrap();
+    script.release_all();
+
+    // main -> Options (third item) -> Audio (sixth item)
+    script.press("menu_down");
+    script.frames(2, 0.0).unwrap();
+    script.release_all();
+    script.press("menu_select");
+    script.frame().unwrap();
+    script.release_all();
+    script.press("menu_down");
+    script.frames(5, 0.0).unwrap();
+    script.release_all();
+    script.press("menu_select");
+    script.frame().unwrap();
+    script.release_all();
+
+    // Master is selected
+    script.press("menu_left");
+    let ops = script.frame().unwrap();
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::SetBusVolume { bus: Api::BUS_MASTER, volume } if (volume - 0.9).abs() < 1e-6)));
+    script.release_all();
+    let ops = script.frame().unwrap();
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::WriteText { msg, .. } if msg == "Master: 90%")));
+
+    script.press("menu_select");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::SetBusMuted { bus: Api::BUS_MASTER, muted: true }));
+    assert!(!ops.contains(&ApiOp::SaveSettings));
+    script.release_all();
+    let ops = script.frame().unwrap();
+    assert!(ops.iter().any(|op| matches!(op, ApiOp::WriteText { msg, .. } if msg == "Master: muted")));
+
+    // closing the menu saves the changes
+    script.press("menu_toggle");
+    let ops = script.frame().unwrap();
+    assert!(ops.contains(&ApiOp::SaveSettings));
+}
+
+#[test]
+fn boss_fight_switches_the_music() {
+    let mut script = game();
+    script.frame().unwrap();
+    let switched = |ops: &[ApiOp]| count(ops, |op| matches!(op, ApiOp::SetMusicLoop(true))) == 1;
+    assert!(!switched(&script.frame().unwrap()));
+
+    // three mines alive: boss music, once
+    for id in 300..303 {
+        script.add_entity(id, entity(Api::ET_MINE_RED, 400.0, 100.0 + id as f32));
+    }
+    assert!(switched(&script.frame().unwrap()));
+    assert!(!switched(&script.frame().unwrap()));
+
+    // one mine left: back to gameplay
+    for id in 300..302 {
+        script.context.entity_data.remove(&id);
+        script.context.think_entities.retain(|&other| other != id);
+    }
+    assert!(switched(&script.frame().unwrap()));
+}